@@ -0,0 +1,174 @@
+use crate::api::{self, PostsApi};
+use crate::generator::{markdown_to_html, split_frontmatter};
+use actix_web::{web, HttpRequest, HttpResponse};
+
+/// The admin page; `ENDPOINT/preview` renders markdown for it
+pub const ENDPOINT: &str = "/__admin";
+
+/// Lists posts, edits one in a textarea next to its rendered preview and saves it back
+/// through the posts API, whose rebuild the open site pages reload after
+const PAGE: &str = r##"<!doctype html>
+<html lang="en">
+<head>
+<meta charset="UTF-8">
+<meta name="viewport" content="width=device-width, initial-scale=1.0">
+<title>Admin</title>
+<style>
+* { box-sizing: border-box; }
+body { margin: 0; height: 100vh; display: grid; grid-template-columns: 16rem 1fr 1fr; font: 15px/1.5 -apple-system, BlinkMacSystemFont, 'Segoe UI', sans-serif; color: #333; }
+aside { border-right: 1px solid #eee; overflow: auto; padding: 1rem; }
+aside ul { list-style: none; padding: 0; }
+aside li a { display: block; padding: 0.3rem 0; color: #333; text-decoration: none; }
+aside li a.current { font-weight: 600; }
+aside small { display: block; color: #999; }
+main { display: flex; flex-direction: column; border-right: 1px solid #eee; }
+main header { display: flex; gap: 0.5rem; align-items: center; padding: 0.5rem 1rem; border-bottom: 1px solid #eee; }
+main header span { margin-left: auto; color: #999; font-size: 0.9rem; }
+textarea { flex: 1; border: 0; padding: 1rem; resize: none; font: 14px/1.6 'Courier New', monospace; outline: none; }
+#preview { overflow: auto; padding: 1rem 2rem; line-height: 1.8; }
+#preview img { max-width: 100%; }
+</style>
+</head>
+<body>
+<aside>
+    <button id="new">New post</button>
+    <ul id="posts"></ul>
+</aside>
+<main>
+    <header><strong id="file">No post open</strong><button id="save" disabled>Save</button><span id="status"></span></header>
+    <textarea id="editor" spellcheck="false" disabled></textarea>
+</main>
+<section id="preview"></section>
+<script>
+(() => {
+    const $ = (id) => document.getElementById(id);
+    let current = null;
+    let previewTimer = null;
+
+    // Asks for the admin token once a request is refused, then tries again with it
+    async function call(url, options = {}) {
+        const token = sessionStorage.getItem("admin-token");
+        const headers = { ...options.headers };
+        if (token) headers.Authorization = "Bearer " + token;
+        const response = await fetch(url, { ...options, headers });
+        if (response.status === 401) {
+            const given = prompt("Admin token");
+            if (given === null) throw new Error("Not signed in");
+            sessionStorage.setItem("admin-token", given);
+            return call(url, options);
+        }
+        if (!response.ok) {
+            const body = await response.json().catch(() => ({}));
+            throw new Error(body.error || response.statusText);
+        }
+        return response;
+    }
+
+    const status = (text) => { $("status").textContent = text; };
+
+    async function listPosts() {
+        const posts = await (await call("/__api/posts")).json();
+        $("posts").replaceChildren(...posts.map((post) => {
+            const item = document.createElement("li");
+            const link = item.appendChild(document.createElement("a"));
+            link.href = "#" + post.file;
+            link.textContent = post.frontmatter.title || post.file;
+            link.className = post.file === current ? "current" : "";
+            const meta = link.appendChild(document.createElement("small"));
+            meta.textContent = [post.frontmatter.date, post.frontmatter.draft ? "draft" : ""].filter(Boolean).join(" · ");
+            link.onclick = (event) => { event.preventDefault(); openPost(post.file); };
+            return item;
+        }));
+    }
+
+    async function openPost(file) {
+        const post = await (await call("/__api/posts/" + file)).json();
+        current = file;
+        $("file").textContent = file;
+        $("editor").value = post.content;
+        $("editor").disabled = $("save").disabled = false;
+        status("");
+        preview();
+        listPosts();
+    }
+
+    async function preview() {
+        const response = await call("/__admin/preview", { method: "POST", body: $("editor").value });
+        $("preview").innerHTML = await response.text();
+    }
+
+    async function save() {
+        await call("/__api/posts/" + current, {
+            method: "PUT",
+            headers: { "Content-Type": "application/json" },
+            body: JSON.stringify({ content: $("editor").value }),
+        });
+        status("Saved, rebuilding…");
+        listPosts();
+    }
+
+    $("editor").oninput = () => {
+        status("Unsaved changes");
+        clearTimeout(previewTimer);
+        previewTimer = setTimeout(() => preview().catch((e) => status(e.message)), 300);
+    };
+    $("save").onclick = () => save().catch((e) => status(e.message));
+    document.onkeydown = (event) => {
+        if ((event.ctrlKey || event.metaKey) && event.key === "s" && current) {
+            event.preventDefault();
+            save().catch((e) => status(e.message));
+        }
+    };
+    $("new").onclick = async () => {
+        const title = prompt("Title");
+        if (!title) return;
+        try {
+            const response = await call("/__api/posts", {
+                method: "POST",
+                headers: { "Content-Type": "application/json" },
+                body: JSON.stringify({ title, draft: true }),
+            });
+            await openPost((await response.json()).file);
+        } catch (e) {
+            status(e.message);
+        }
+    };
+
+    listPosts()
+        .then(() => location.hash && openPost(location.hash.slice(1)))
+        .catch((e) => status(e.message));
+})();
+</script>
+</body>
+</html>
+"##;
+
+pub fn routes(config: &mut web::ServiceConfig, api: web::Data<PostsApi>) {
+    config.service(
+        web::scope(ENDPOINT)
+            .app_data(api)
+            .route("", web::get().to(page))
+            .route("/preview", web::post().to(preview)),
+    );
+}
+
+async fn page() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header(("Cache-Control", "no-cache"))
+        .body(PAGE)
+}
+
+/// Renders a post's markdown the way the build does, without its frontmatter
+async fn preview(req: HttpRequest, api: web::Data<PostsApi>, markdown: String) -> HttpResponse {
+    if let Some(response) = api::unauthorized(&req, &api) {
+        return response;
+    }
+    let html = match split_frontmatter(&markdown) {
+        Some((_, body)) => markdown_to_html(&body),
+        None => markdown_to_html(&markdown),
+    };
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(html)
+}
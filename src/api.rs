@@ -0,0 +1,369 @@
+use crate::config::ServerSettings;
+use crate::generator::{
+    frontmatter_fields, is_valid_slug, markdown_files, parse_date, parse_list, slugify,
+    split_frontmatter, unquote,
+};
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc;
+
+/// Lists and creates posts; `ENDPOINT/<file>` reads, replaces and deletes one
+pub const ENDPOINT: &str = "/__api/posts";
+
+/// Largest post the API accepts, as JSON
+const MAX_POST_SIZE: usize = 1024 * 1024;
+
+/// Lets remote authoring tools and the admin page manage the markdown files in `posts/`.
+/// Every change starts a rebuild
+pub struct PostsApi {
+    pub posts_dir: PathBuf,
+    /// Required as `Authorization: Bearer <token>`
+    pub token: String,
+    /// The `ignore_files` patterns, whose files aren't posts
+    pub ignore_files: Vec<String>,
+    pub rebuild: mpsc::Sender<Vec<PathBuf>>,
+}
+
+impl PostsApi {
+    /// The API `settings` ask for, which there's none of without an `admin_token`, even
+    /// with `admin` on: behind a proxy or tunnel every request seems to come from this
+    /// machine, so where one comes from can't stand in for a token
+    pub fn configured(
+        settings: &ServerSettings,
+        rebuild: mpsc::Sender<Vec<PathBuf>>,
+    ) -> Option<PostsApi> {
+        Some(PostsApi {
+            posts_dir: PathBuf::from(&settings.posts_dir),
+            token: settings.admin_token.clone()?,
+            ignore_files: settings.ignore_files.clone(),
+            rebuild,
+        })
+    }
+
+    /// The file `file` names, relative to `posts_dir`, if it's a markdown file inside it
+    fn post_path(&self, file: &str) -> Option<PathBuf> {
+        let relative = Path::new(file);
+        let inside = relative.components().all(|component| match component {
+            Component::Normal(name) => !name.to_string_lossy().starts_with('.'),
+            _ => false,
+        });
+        (inside && relative.extension().is_some_and(|e| e == "md"))
+            .then(|| self.posts_dir.join(relative))
+    }
+
+    /// The name of `path` relative to `posts_dir`, with `/` separators
+    fn file_name(&self, path: &Path) -> String {
+        path.strip_prefix(&self.posts_dir)
+            .unwrap_or(path)
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    fn changed(&self, what: &str, path: &Path) {
+        println!("\n✏️  {} {} through {}", what, path.display(), ENDPOINT);
+        let _ = self.rebuild.send(vec![path.to_path_buf()]);
+    }
+}
+
+pub fn routes(config: &mut web::ServiceConfig, api: web::Data<PostsApi>) {
+    config.service(
+        web::scope(ENDPOINT)
+            .app_data(api)
+            .app_data(web::JsonConfig::default().limit(MAX_POST_SIZE))
+            .route("", web::get().to(list))
+            .route("", web::post().to(create))
+            .route("/{file:.*}", web::get().to(read))
+            .route("/{file:.*}", web::put().to(update))
+            .route("/{file:.*}", web::delete().to(delete)),
+    );
+}
+
+/// A post to create. The slug defaults to the title's and the date to today
+#[derive(Deserialize)]
+struct NewPost {
+    title: String,
+    slug: Option<String>,
+    date: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    draft: bool,
+    /// Markdown after the frontmatter
+    #[serde(default)]
+    body: String,
+}
+
+/// The whole file, frontmatter included
+#[derive(Deserialize)]
+struct PostContent {
+    content: String,
+}
+
+/// Every post with its frontmatter, by file name
+async fn list(req: HttpRequest, api: web::Data<PostsApi>) -> HttpResponse {
+    if let Some(response) = unauthorized(&req, &api) {
+        return response;
+    }
+    let mut files = match markdown_files(&api.posts_dir, &api.ignore_files) {
+        Ok(files) => files,
+        Err(e) => return error(HttpResponse::InternalServerError(), e),
+    };
+    files.sort();
+    let mut posts = Vec::new();
+    for path in files {
+        let Ok(content) = tokio::fs::read_to_string(&path).await else {
+            continue;
+        };
+        posts.push(json!({
+            "file": api.file_name(&path),
+            "frontmatter": frontmatter(&content),
+        }));
+    }
+    HttpResponse::Ok().json(posts)
+}
+
+async fn read(req: HttpRequest, api: web::Data<PostsApi>, file: web::Path<String>) -> HttpResponse {
+    if let Some(response) = unauthorized(&req, &api) {
+        return response;
+    }
+    let Some(path) = api.post_path(&file) else {
+        return error(HttpResponse::NotFound(), "no such post");
+    };
+    match tokio::fs::read_to_string(&path).await {
+        Ok(content) => HttpResponse::Ok().json(json!({
+            "file": api.file_name(&path),
+            "frontmatter": frontmatter(&content),
+            "content": content,
+        })),
+        Err(_) => error(HttpResponse::NotFound(), "no such post"),
+    }
+}
+
+/// Writes a new `<slug>.md` with the frontmatter filled in, refusing to replace a post
+async fn create(
+    req: HttpRequest,
+    api: web::Data<PostsApi>,
+    post: web::Json<NewPost>,
+) -> HttpResponse {
+    if let Some(response) = unauthorized(&req, &api) {
+        return response;
+    }
+    let title = post.title.trim();
+    if title.is_empty() || title.contains('\n') {
+        return error(
+            HttpResponse::UnprocessableEntity(),
+            "`title` must be one line",
+        );
+    }
+    let slug = post.slug.clone().unwrap_or_else(|| slugify(title));
+    if !is_valid_slug(&slug) {
+        return error(
+            HttpResponse::UnprocessableEntity(),
+            format!("invalid slug \"{}\"", slug),
+        );
+    }
+    let date = match &post.date {
+        Some(date) if parse_date(date).is_none() => {
+            return error(
+                HttpResponse::UnprocessableEntity(),
+                format!("`date` must be a date like 2024-05-01, found \"{}\"", date),
+            )
+        }
+        Some(date) => date.clone(),
+        None => chrono::Local::now().format("%Y-%m-%d").to_string(),
+    };
+
+    if post.tags.iter().any(|tag| tag.contains([',', '\n'])) {
+        return error(
+            HttpResponse::UnprocessableEntity(),
+            "`tags` can't contain commas or line breaks",
+        );
+    }
+    let mut frontmatter = serde_yaml::Mapping::new();
+    frontmatter.insert("title".into(), title.into());
+    frontmatter.insert("date".into(), date.into());
+    if !post.tags.is_empty() {
+        frontmatter.insert("tags".into(), post.tags.clone().into());
+    }
+    if post.draft {
+        frontmatter.insert("draft".into(), true.into());
+    }
+    let frontmatter = match serde_yaml::to_string(&frontmatter) {
+        Ok(frontmatter) => frontmatter,
+        Err(e) => return error(HttpResponse::InternalServerError(), e),
+    };
+    let content = format!("---\n{}---\n\n{}", frontmatter, post.body);
+
+    let path = api.posts_dir.join(format!("{}.md", slug));
+    let file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .await;
+    let result = match file {
+        Ok(mut file) => tokio::io::AsyncWriteExt::write_all(&mut file, content.as_bytes()).await,
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            return error(
+                HttpResponse::Conflict(),
+                format!("{} already exists", api.file_name(&path)),
+            )
+        }
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        return error(HttpResponse::InternalServerError(), e);
+    }
+    api.changed("Created", &path);
+    let file = api.file_name(&path);
+    HttpResponse::Created()
+        .insert_header(("Location", format!("{}/{}", ENDPOINT, file)))
+        .json(json!({ "file": file, "content": content }))
+}
+
+/// Replaces an existing post's file
+async fn update(
+    req: HttpRequest,
+    api: web::Data<PostsApi>,
+    file: web::Path<String>,
+    post: web::Json<PostContent>,
+) -> HttpResponse {
+    if let Some(response) = unauthorized(&req, &api) {
+        return response;
+    }
+    let Some(path) = api.post_path(&file).filter(|path| path.is_file()) else {
+        return error(HttpResponse::NotFound(), "no such post");
+    };
+    if split_frontmatter(&post.content).is_none() {
+        return error(
+            HttpResponse::UnprocessableEntity(),
+            "`content` must start with a `---` frontmatter block",
+        );
+    }
+    if let Err(e) = tokio::fs::write(&path, &post.content).await {
+        return error(HttpResponse::InternalServerError(), e);
+    }
+    api.changed("Updated", &path);
+    HttpResponse::NoContent().finish()
+}
+
+async fn delete(
+    req: HttpRequest,
+    api: web::Data<PostsApi>,
+    file: web::Path<String>,
+) -> HttpResponse {
+    if let Some(response) = unauthorized(&req, &api) {
+        return response;
+    }
+    let Some(path) = api.post_path(&file).filter(|path| path.is_file()) else {
+        return error(HttpResponse::NotFound(), "no such post");
+    };
+    if let Err(e) = tokio::fs::remove_file(&path).await {
+        return error(HttpResponse::InternalServerError(), e);
+    }
+    api.changed("Deleted", &path);
+    HttpResponse::NoContent().finish()
+}
+
+/// A 401 response unless the request bears the API's token
+pub fn unauthorized(req: &HttpRequest, api: &PostsApi) -> Option<HttpResponse> {
+    (!crate::has_bearer_token(req, &api.token)).then(|| {
+        HttpResponse::Unauthorized()
+            .insert_header(("WWW-Authenticate", "Bearer"))
+            .json(json!({ "error": "missing or wrong token" }))
+    })
+}
+
+fn error(mut response: actix_web::HttpResponseBuilder, message: impl ToString) -> HttpResponse {
+    response.json(json!({ "error": message.to_string() }))
+}
+
+/// A post's frontmatter as JSON: lists as arrays, flags as booleans, the rest as strings
+fn frontmatter(content: &str) -> Value {
+    let mut fields = Map::new();
+    let lines = split_frontmatter(content)
+        .map(|(lines, _)| lines)
+        .unwrap_or_default();
+    for (_, field) in frontmatter_fields(&lines) {
+        let Ok((key, raw_value)) = field else {
+            continue;
+        };
+        let value = unquote(&raw_value);
+        let value = match key {
+            "tags" | "keywords" | "aliases" => json!(parse_list(&raw_value)),
+            "draft" | "featured" => value.parse().map_or_else(|_| json!(value), Value::Bool),
+            _ => json!(value),
+        };
+        fields.insert(key.to_string(), value);
+    }
+    Value::Object(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::{Method, StatusCode};
+    use actix_web::App;
+
+    /// Settings with a posts directory holding one post
+    fn settings(name: &str, token: Option<&str>) -> ServerSettings {
+        let dir = std::env::temp_dir().join(format!("blog-api-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("hello.md"), "---\ntitle: Hello\n---\nHi").unwrap();
+        ServerSettings {
+            posts_dir: dir.display().to_string(),
+            admin: true,
+            admin_token: token.map(str::to_string),
+            ..ServerSettings::default()
+        }
+    }
+
+    #[test]
+    fn is_off_without_a_token() {
+        let (rebuild, _) = mpsc::channel();
+        assert!(PostsApi::configured(&settings("tokenless", None), rebuild).is_none());
+    }
+
+    #[actix_web::test]
+    async fn requires_the_token_even_from_this_machine() {
+        use actix_web::test;
+
+        let settings = settings("token", Some("secret"));
+        let (rebuild, rebuilds) = mpsc::channel();
+        let api = PostsApi::configured(&settings, rebuild).unwrap();
+        let app =
+            test::init_service(App::new().configure(|config| routes(config, web::Data::new(api))))
+                .await;
+        let request = |method: Method, authorization: Option<&str>| {
+            let mut request = test::TestRequest::default()
+                .method(method)
+                .uri(&format!("{}/hello.md", ENDPOINT))
+                .peer_addr("127.0.0.1:50000".parse().unwrap());
+            if let Some(authorization) = authorization {
+                request = request.insert_header(("Authorization", authorization));
+            }
+            request.to_request()
+        };
+
+        for authorization in [None, Some("Bearer wrong"), Some("Basic c2VjcmV0")] {
+            for method in [Method::GET, Method::DELETE] {
+                let response = test::call_service(&app, request(method, authorization)).await;
+                assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+            }
+        }
+        assert!(Path::new(&settings.posts_dir).join("hello.md").is_file());
+        assert!(rebuilds.try_recv().is_err());
+
+        let response = test::call_service(&app, request(Method::GET, Some("Bearer secret"))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response =
+            test::call_service(&app, request(Method::DELETE, Some("Bearer secret"))).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert!(!Path::new(&settings.posts_dir).join("hello.md").exists());
+        assert!(rebuilds.try_recv().is_ok());
+    }
+}
@@ -0,0 +1,103 @@
+use actix_web::web::Bytes;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+/// Files larger than this, like videos, stay on disk and are streamed from there
+const MAX_CACHED_SIZE: u64 = 4 * 1024 * 1024;
+
+/// A generated file as of the build that produced it
+pub struct CachedFile {
+    /// `None` for files too large to keep in memory
+    pub content: Option<Bytes>,
+    pub len: u64,
+    pub modified: Option<SystemTime>,
+}
+
+/// The output directory as of one finished build, keyed by full path
+pub struct Files {
+    root: PathBuf,
+    files: HashMap<PathBuf, CachedFile>,
+}
+
+impl Files {
+    fn load(dir: &Path) -> io::Result<Files> {
+        let mut files = Files {
+            root: dir.canonicalize()?,
+            files: HashMap::new(),
+        };
+        let root = files.root.clone();
+        files.read_dir(&root)?;
+        Ok(files)
+    }
+
+    /// Reads the files in `dir` and its subdirectories. Symlinks are skipped, so nothing
+    /// outside the output directory ends up served
+    fn read_dir(&mut self, dir: &Path) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            let path = entry.path();
+            if file_type.is_dir() {
+                self.read_dir(&path)?;
+            } else if file_type.is_file() {
+                let metadata = entry.metadata()?;
+                let content = if metadata.len() <= MAX_CACHED_SIZE {
+                    Some(Bytes::from(fs::read(&path)?))
+                } else {
+                    None
+                };
+                let file = CachedFile {
+                    content,
+                    len: metadata.len(),
+                    modified: metadata.modified().ok(),
+                };
+                self.files.insert(path, file);
+            }
+        }
+        Ok(())
+    }
+
+    /// Full path of the file `path` (relative, percent-decoded) names, if the build made one
+    pub fn resolve(&self, path: &str) -> Option<PathBuf> {
+        let path = self.root.join(path);
+        self.files.contains_key(&path).then_some(path)
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&CachedFile> {
+        self.files.get(path)
+    }
+}
+
+/// In-memory copy of the output directory that the dev server sends files from. It's
+/// replaced whole once a build has finished, so requests during a build get the previous
+/// one instead of half-written files
+#[derive(Clone)]
+pub struct OutputCache {
+    dir: PathBuf,
+    current: Arc<RwLock<Arc<Files>>>,
+}
+
+impl OutputCache {
+    pub fn new(dir: &Path) -> io::Result<Self> {
+        Ok(OutputCache {
+            dir: dir.to_path_buf(),
+            current: Arc::new(RwLock::new(Arc::new(Files::load(dir)?))),
+        })
+    }
+
+    /// Reads the output directory again after a build
+    pub fn refresh(&self) -> io::Result<()> {
+        let files = Files::load(&self.dir)?;
+        *self.current.write().unwrap() = Arc::new(files);
+        Ok(())
+    }
+
+    /// The files of the latest build, which stay the same for as long as they're held
+    pub fn files(&self) -> Arc<Files> {
+        self.current.read().unwrap().clone()
+    }
+}
@@ -0,0 +1,63 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+/// Most changed files named in the console before the rest are counted
+const MAX_LISTED: usize = 3;
+
+/// Source files changed since the last build, gathered from the watcher and the posts API
+/// over one debounce window. Rebuilds asked for without naming files, like the webhook's,
+/// may have changed anything
+#[derive(Default)]
+pub struct Changes {
+    paths: BTreeSet<PathBuf>,
+    everything: bool,
+}
+
+impl Changes {
+    /// Adds the paths of one rebuild request, where none means anything may have changed
+    pub fn add(&mut self, paths: Vec<PathBuf>) {
+        if paths.is_empty() {
+            self.everything = true;
+        }
+        self.paths.extend(paths.iter().map(|path| normalize(path)));
+    }
+
+    /// Whether `path`, or a file under it, may have changed
+    pub fn touches(&self, path: &Path) -> bool {
+        let path = normalize(path);
+        self.everything || self.paths.iter().any(|changed| changed.starts_with(&path))
+    }
+}
+
+impl fmt::Display for Changes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.everything || self.paths.is_empty() {
+            return write!(f, "the sources");
+        }
+        let listed: Vec<String> = self
+            .paths
+            .iter()
+            .take(MAX_LISTED)
+            .map(|path| path.display().to_string())
+            .collect();
+        write!(f, "{}", listed.join(", "))?;
+        if self.paths.len() > MAX_LISTED {
+            write!(f, " and {} more", self.paths.len() - MAX_LISTED)?;
+        }
+        Ok(())
+    }
+}
+
+/// `path` relative to the working directory, like the configured directories, without
+/// `./` parts
+fn normalize(path: &Path) -> PathBuf {
+    let relative = std::env::current_dir()
+        .ok()
+        .and_then(|dir| path.strip_prefix(dir).ok())
+        .unwrap_or(path);
+    relative
+        .components()
+        .filter(|component| !matches!(component, Component::CurDir))
+        .collect()
+}
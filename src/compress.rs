@@ -0,0 +1,77 @@
+use crate::generator::track_output;
+use crate::images::is_up_to_date;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// Extensions of text files worth compressing; images are already compressed
+const COMPRESSIBLE: &[&str] = &["html", "css", "js", "json", "xml", "txt", "svg"];
+
+// Files this small gain nothing from compression
+const MIN_SIZE: u64 = 256;
+
+/// Writes `.gz` and `.br` siblings for the compressible files in `outputs`, skipping
+/// ones whose compressed copies are already up to date. Returns how many files were
+/// compressed. The dev server sends these when the client accepts them; static hosts can
+/// do the same, e.g. nginx with `gzip_static on;` and `brotli_static on;`
+pub fn compress_outputs(outputs: &[PathBuf]) -> io::Result<usize> {
+    let mut compressed = 0;
+
+    for path in outputs.iter().filter(|path| is_compressible(path)) {
+        let gz_path = sibling(path, "gz");
+        let br_path = sibling(path, "br");
+        track_output(&gz_path);
+        track_output(&br_path);
+        if is_up_to_date(path, &gz_path) && is_up_to_date(path, &br_path) {
+            continue;
+        }
+
+        let content = fs::read(path)?;
+
+        let mut gz = GzEncoder::new(Vec::new(), Compression::best());
+        gz.write_all(&content)?;
+        fs::write(&gz_path, gz.finish()?)?;
+
+        let mut br = Vec::new();
+        {
+            let mut writer = brotli::CompressorWriter::new(&mut br, 4096, 11, 22);
+            writer.write_all(&content)?;
+        }
+        fs::write(&br_path, br)?;
+
+        compressed += 1;
+    }
+
+    Ok(compressed)
+}
+
+/// Whether responses of `content_type` are text worth compressing on the fly: HTML, CSS,
+/// JavaScript, JSON, XML and plain text
+pub fn is_compressible_type(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+    matches!(
+        essence,
+        "text/html" | "text/css" | "text/plain" | "image/svg+xml"
+    ) || essence.ends_with("javascript")
+        || essence.ends_with("json")
+        || essence.ends_with("xml")
+}
+
+fn is_compressible(path: &Path) -> bool {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default();
+    COMPRESSIBLE.contains(&extension)
+        && fs::metadata(path).is_ok_and(|metadata| metadata.len() >= MIN_SIZE)
+}
+
+/// `index.html` becomes `index.html.gz`
+fn sibling(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
@@ -0,0 +1,980 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Site settings file, read from the working directory at startup
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// Profiles that exist without being declared in the config file
+const BUILT_IN_PROFILES: &[&str] = &["dev", "preview", "production"];
+
+/// Every setting with the TOML type it must have
+const SETTINGS: &[(&str, &str)] = &[
+    ("title", "string"),
+    ("description", "string"),
+    ("author", "string"),
+    ("base_url", "string"),
+    ("host", "string"),
+    ("port", "integer"),
+    ("https", "boolean"),
+    ("tls_cert", "string"),
+    ("tls_key", "string"),
+    ("basic_auth", "string"),
+    ("webhook_token", "string"),
+    ("admin_token", "string"),
+    ("rate_limit", "integer"),
+    ("max_uri_length", "integer"),
+    ("max_request_size", "integer"),
+    ("posts_per_page", "integer"),
+    ("feed_limit", "integer"),
+    ("feed_full_content", "boolean"),
+    ("permalink", "string"),
+    ("trailing_slash", "string"),
+    ("security_contact", "string"),
+    ("posts_dir", "string"),
+    ("pages_dir", "string"),
+    ("templates_dir", "string"),
+    ("static_dir", "string"),
+    ("output_dir", "string"),
+    ("data_dir", "string"),
+    ("ignore_files", "array"),
+    ("theme", "string"),
+    ("themes_dir", "string"),
+    ("menu", "array"),
+    ("redirects", "array"),
+    ("analytics_snippet", "string"),
+    ("minify", "boolean"),
+    ("webp", "boolean"),
+    ("drafts", "boolean"),
+    ("future", "boolean"),
+    ("analytics", "boolean"),
+    ("noindex", "boolean"),
+    ("robots_disallow", "boolean"),
+    ("live_reload", "boolean"),
+    ("admin", "boolean"),
+    ("compress", "boolean"),
+    ("memory_cache", "boolean"),
+    ("security_headers", "table"),
+    ("cache_rules", "array"),
+    ("content_types", "table"),
+    ("extra", "table"),
+];
+
+/// Settings from `config.toml`. Every key is optional; missing ones keep their defaults.
+/// Templates get the whole thing as `site`
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SiteConfig {
+    pub title: String,
+    pub description: String,
+    pub author: Option<String>,
+    /// Absolute URL the site is served from, used for every absolute link (feeds, sitemap,
+    /// OpenGraph and canonical tags, the `absolute_url` template filter). `--base-url` and
+    /// the `BASE_URL` environment variable take precedence, and without any of them the
+    /// local server's address is used
+    pub base_url: String,
+    /// Settings only read when the server starts
+    #[serde(flatten)]
+    pub server: ServerSettings,
+    /// Number of posts listed per index page
+    pub posts_per_page: usize,
+    /// Number of most recent posts included in feeds
+    pub feed_limit: usize,
+    /// Put the full post body in feeds instead of the excerpt
+    pub feed_full_content: bool,
+    /// URL pattern for posts, see `BuildOptions::permalink`
+    pub permalink: String,
+    /// Where to report vulnerabilities, a `mailto:` or `https:` URI. `.well-known/security.txt`
+    /// is only written when this is set, since a contact is required
+    pub security_contact: Option<String>,
+    /// Where standalone pages are read from
+    pub pages_dir: String,
+    /// Copied as-is to the site root
+    pub static_dir: String,
+    /// Header navigation, `[[menu]]` entries sorted by `order`. Without any, pages are listed
+    pub menu: Vec<MenuItem>,
+    /// Old URLs that redirect elsewhere, `[[redirects]]` entries. Post aliases are added to
+    /// these
+    pub redirects: Vec<Redirect>,
+    /// HTML (e.g. a `<script>` tag) added to the `<head>` of every page when `analytics` is on
+    pub analytics_snippet: Option<String>,
+
+    /// Build profile the settings were loaded for
+    #[serde(skip_deserializing)]
+    pub profile: String,
+    /// Minify generated HTML. On in `production`
+    pub minify: bool,
+    /// Write WebP copies of JPEG and PNG images and serve them through `<picture>`. `--webp`
+    /// turns it on too
+    pub webp: bool,
+    /// Build a preview of each post marked `draft: true` at `/drafts/<slug>/`, left out of
+    /// listings, feeds and the sitemap. On in `dev`
+    pub drafts: bool,
+    /// Include posts dated in the future. On in `dev`
+    pub future: bool,
+    /// Add `analytics_snippet` to pages. On in `production`
+    pub analytics: bool,
+    /// Tell search engines not to index pages, with a robots meta tag. On in `preview`
+    pub noindex: bool,
+    /// Disallow all crawlers in the generated `robots.txt`. On in `preview`
+    pub robots_disallow: bool,
+
+    /// Custom values from the `[extra]` table, given to every template as variables of their
+    /// own (e.g. `{{ accent_color }}`) and as `site.extra`
+    pub extra: toml::Table,
+}
+
+/// Settings the server and file watcher are started with, which only take effect after a
+/// restart. They're top-level keys in `config.toml` like the rest; a config reload compares
+/// and keeps them as a whole
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerSettings {
+    /// Address the server listens on; `--host` and the `HOST` environment variable override it
+    pub host: String,
+    /// Port the server listens on, or the next free one if it's taken. `--port` and the
+    /// `PORT` environment variable override it
+    pub port: u16,
+    /// Serve HTTPS instead of HTTP, for testing features that need a secure context from
+    /// other devices. Uses `tls_cert` and `tls_key`, or else a self-signed certificate
+    pub https: bool,
+    /// PEM file with the certificate chain to serve HTTPS with, along with `tls_key`
+    pub tls_cert: Option<String>,
+    /// PEM file with the private key of `tls_cert`
+    pub tls_key: Option<String>,
+    /// `user:password` the server asks every visitor for, e.g. to share a preview with
+    /// drafts through a tunnel. The `BASIC_AUTH` environment variable overrides it
+    #[serde(skip_serializing)]
+    pub basic_auth: Option<String>,
+    /// Token `POST /__hooks/rebuild` must bring as `Authorization: Bearer <token>` to
+    /// rebuild the site, e.g. from a git hook. The endpoint is off without one. The
+    /// `WEBHOOK_TOKEN` environment variable overrides it
+    #[serde(skip_serializing)]
+    pub webhook_token: Option<String>,
+    /// Token the posts API at `/__api/posts` and the admin page at `/__admin` require as
+    /// `Authorization: Bearer <token>`, for authoring tools to list, create, edit and delete
+    /// posts. Setting it turns both on. The `ADMIN_TOKEN` environment variable overrides it
+    #[serde(skip_serializing)]
+    pub admin_token: Option<String>,
+    /// Requests a minute the server answers from each client address, with 429 Too Many
+    /// Requests beyond that. 0 turns the limit off
+    pub rate_limit: u32,
+    /// Longest request URI the server accepts, in bytes
+    pub max_uri_length: usize,
+    /// Largest request body the server accepts, in bytes, going by `Content-Length`
+    pub max_request_size: u64,
+    /// Whether page URLs end with a slash, `always` (`/hello/`) or `never` (`/hello`). The
+    /// generated links follow it, and the server redirects the other form with a 301
+    pub trailing_slash: String,
+    /// Where posts (and their images) are read from
+    pub posts_dir: String,
+    pub templates_dir: String,
+    /// Where the site is generated to and served from
+    pub output_dir: String,
+    /// JSON, TOML and YAML files given to templates as `data`
+    pub data_dir: String,
+    /// Names of files that are neither watched nor read or copied from the source
+    /// directories, where `*` stands for any characters and `?` for one, like editor swap and
+    /// backup files
+    pub ignore_files: Vec<String>,
+    /// Name of a theme in `themes_dir`, whose `templates/` and `static/` are used for any
+    /// file the site doesn't have itself
+    pub theme: Option<String>,
+    pub themes_dir: String,
+    /// Refresh open pages in the browser after each rebuild. On in `dev`
+    pub live_reload: bool,
+    /// Serve the admin page at `/__admin` and the posts API it uses. Both need
+    /// `admin_token`, and setting it turns them on by itself
+    pub admin: bool,
+    /// Compress text responses from the server with gzip or brotli when the client accepts
+    /// them. Files the build pre-compressed are sent as they are
+    pub compress: bool,
+    /// Keep the latest build in memory and serve it from there, switching over once a
+    /// rebuild has finished. On in `dev`
+    pub memory_cache: bool,
+    /// Hardening headers the server adds to its responses. On in `preview` and `production`
+    pub security_headers: SecurityHeaders,
+    /// `Cache-Control` values for matching files, `[[cache_rules]]` entries. The first
+    /// matching rule wins over the server's defaults, except in `dev` where files are
+    /// always revalidated
+    pub cache_rules: Vec<CacheRule>,
+    /// `Content-Type` values for files by extension (`webmanifest`, `m4a`), sent as they
+    /// are in place of the server's own guess, charset included
+    pub content_types: BTreeMap<String, String>,
+}
+
+/// One link in the header navigation
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MenuItem {
+    pub label: String,
+    pub url: String,
+    /// Position in the menu, lower first
+    #[serde(default)]
+    pub order: i32,
+    /// Links to another site, opened in a new tab
+    #[serde(default)]
+    pub external: bool,
+}
+
+/// A redirect from a path on the site, answered by the server and by a stub page in the
+/// output for static hosts
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Redirect {
+    pub from: String,
+    /// A site path or a URL
+    pub to: String,
+    /// 301 unless given
+    #[serde(default = "default_redirect_status")]
+    pub status: u16,
+}
+
+fn default_redirect_status() -> u16 {
+    301
+}
+
+/// A `Cache-Control` value for the output files whose site path matches `path`, where `*`
+/// stands for any characters and `?` for one, e.g. `/images/*` or `*.html`
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct CacheRule {
+    pub path: String,
+    pub cache_control: String,
+}
+
+impl CacheRule {
+    pub fn matches(&self, path: &str) -> bool {
+        glob_matches(&self.path, path)
+    }
+}
+
+/// Whether `path`'s file name matches one of the `ignore_files` patterns
+pub fn is_ignored(patterns: &[String], path: &Path) -> bool {
+    path.file_name().is_some_and(|name| {
+        let name = name.to_string_lossy();
+        patterns.iter().any(|pattern| glob_matches(pattern, &name))
+    })
+}
+
+/// Whether `text` matches `pattern`, where `*` stands for any characters and `?` for any one
+/// character. Unlike in a shell, they match a leading `.` too, so `*.swp` catches Vim's
+/// `.post.md.swp`
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Where the last `*` was and how much of the text it has taken, to backtrack to
+    let mut star = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    star = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// The `[security_headers]` table. An empty value leaves its header out
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SecurityHeaders {
+    pub enabled: bool,
+    /// Not sent unless set, since what a site needs depends on its templates and embeds
+    pub content_security_policy: String,
+    pub x_content_type_options: String,
+    pub referrer_policy: String,
+    pub x_frame_options: String,
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> Self {
+        SecurityHeaders {
+            enabled: false,
+            content_security_policy: String::new(),
+            x_content_type_options: "nosniff".to_string(),
+            referrer_policy: "strict-origin-when-cross-origin".to_string(),
+            x_frame_options: "SAMEORIGIN".to_string(),
+        }
+    }
+}
+
+impl SecurityHeaders {
+    /// The headers to send, if they're enabled
+    pub fn headers(&self) -> Vec<(&'static str, String)> {
+        if !self.enabled {
+            return Vec::new();
+        }
+        [
+            ("Content-Security-Policy", &self.content_security_policy),
+            ("X-Content-Type-Options", &self.x_content_type_options),
+            ("Referrer-Policy", &self.referrer_policy),
+            ("X-Frame-Options", &self.x_frame_options),
+        ]
+        .into_iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(name, value)| (name, value.clone()))
+        .collect()
+    }
+}
+
+impl Default for SiteConfig {
+    fn default() -> Self {
+        SiteConfig {
+            title: "Blog".to_string(),
+            description: "Articles and thoughts".to_string(),
+            author: None,
+            base_url: String::new(),
+            server: ServerSettings::default(),
+            posts_per_page: 10,
+            feed_limit: 20,
+            feed_full_content: false,
+            permalink: "/:slug/".to_string(),
+            security_contact: None,
+            pages_dir: "pages".to_string(),
+            static_dir: "static".to_string(),
+            menu: Vec::new(),
+            redirects: Vec::new(),
+            analytics_snippet: None,
+            profile: "dev".to_string(),
+            minify: false,
+            webp: false,
+            drafts: true,
+            future: true,
+            analytics: false,
+            noindex: false,
+            robots_disallow: false,
+            extra: toml::Table::new(),
+        }
+    }
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        ServerSettings {
+            host: "0.0.0.0".to_string(),
+            port: 8000,
+            https: false,
+            tls_cert: None,
+            tls_key: None,
+            basic_auth: None,
+            webhook_token: None,
+            admin_token: None,
+            rate_limit: 0,
+            max_uri_length: 2048,
+            max_request_size: 1024 * 1024,
+            trailing_slash: "always".to_string(),
+            posts_dir: "posts".to_string(),
+            templates_dir: "templates".to_string(),
+            output_dir: "output".to_string(),
+            data_dir: "data".to_string(),
+            ignore_files: [
+                "*.swp",
+                "*.swo",
+                "*.swx",
+                "*~",
+                ".#*",
+                "#*#",
+                ".DS_Store",
+                "Thumbs.db",
+            ]
+            .map(str::to_string)
+            .to_vec(),
+            theme: None,
+            themes_dir: "themes".to_string(),
+            live_reload: true,
+            admin: false,
+            compress: true,
+            memory_cache: true,
+            security_headers: SecurityHeaders::default(),
+            cache_rules: Vec::new(),
+            content_types: BTreeMap::new(),
+        }
+    }
+}
+
+/// Reads the config file for a build profile, falling back to the defaults when there
+/// isn't one. Settings under `[profiles.<profile>]` override the top-level ones
+pub fn load(path: &Path, profile: &str) -> io::Result<SiteConfig> {
+    let invalid = |message: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), message),
+        )
+    };
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    let mut file: toml::Table = toml::from_str(&contents).map_err(|e| invalid(e.to_string()))?;
+
+    let mut settings = profile_defaults(profile);
+    let profiles = file.remove("profiles");
+    merge(&mut settings, file);
+    match profiles.as_ref().and_then(|profiles| profiles.get(profile)) {
+        Some(toml::Value::Table(overrides)) => merge(&mut settings, overrides.clone()),
+        Some(_) => return Err(invalid(format!("[profiles.{}] must be a table", profile))),
+        None if BUILT_IN_PROFILES.contains(&profile) => {}
+        None => return Err(invalid(format!("unknown profile \"{}\"", profile))),
+    }
+
+    let problems = validate(&settings, &contents, path, profile);
+    if !problems.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} problems:\n{}", problems.len(), problems.join("\n")),
+        ));
+    }
+
+    let mut config: SiteConfig = toml::Value::Table(settings)
+        .try_into()
+        .map_err(|e: toml::de::Error| invalid(e.to_string()))?;
+    config.profile = profile.to_string();
+    config.menu.sort_by_key(|item| item.order);
+    Ok(config)
+}
+
+/// Checks the merged settings, returning every problem found rather than stopping at the
+/// first. Unknown keys are only warned about since they do no harm
+fn validate(settings: &toml::Table, contents: &str, path: &Path, profile: &str) -> Vec<String> {
+    let location = |key: &str| match line_of(contents, key, profile) {
+        Some(line) => format!("{}:{}", path.display(), line),
+        None => path.display().to_string(),
+    };
+    let mut problems = Vec::new();
+
+    for (key, value) in settings {
+        match SETTINGS.iter().find(|(name, _)| name == key) {
+            None => eprintln!("⚠️  {}: unknown setting `{}`", location(key), key),
+            Some((_, expected)) if value.type_str() != *expected => problems.push(format!(
+                "{}: `{}` must be {}, found {}",
+                location(key),
+                key,
+                with_article(expected),
+                with_article(value.type_str())
+            )),
+            Some(_) => {}
+        }
+    }
+
+    let string = |key: &str| settings.get(key).and_then(|value| value.as_str());
+    if let Some(url) = string("base_url") {
+        if !url.is_empty() && !url.starts_with("http://") && !url.starts_with("https://") {
+            problems.push(format!(
+                "{}: `base_url` must be an absolute URL like https://example.com, found \"{}\"",
+                location("base_url"),
+                url
+            ));
+        }
+    }
+    if let Some(port) = settings.get("port").and_then(|value| value.as_integer()) {
+        if !(0..=65535).contains(&port) {
+            problems.push(format!(
+                "{}: `port` must be between 0 and 65535, found {}",
+                location("port"),
+                port
+            ));
+        }
+    }
+    if let Some(credentials) = string("basic_auth") {
+        if credentials
+            .split_once(':')
+            .is_none_or(|(user, _)| user.is_empty())
+        {
+            problems.push(format!(
+                "{}: `basic_auth` must look like \"user:password\"",
+                location("basic_auth")
+            ));
+        }
+    }
+    if string("tls_cert").is_some() != string("tls_key").is_some() {
+        problems.push(format!(
+            "{}: `tls_cert` and `tls_key` must be set together",
+            location(if string("tls_cert").is_some() {
+                "tls_cert"
+            } else {
+                "tls_key"
+            })
+        ));
+    }
+    for key in ["tls_cert", "tls_key"] {
+        if let Some(file) = string(key).filter(|file| !Path::new(file).is_file()) {
+            problems.push(format!(
+                "{}: `{}` file {} not found",
+                location(key),
+                key,
+                file
+            ));
+        }
+    }
+    for key in ["webhook_token", "admin_token"] {
+        if string(key).is_some_and(|token| token.trim().is_empty()) {
+            problems.push(format!("{}: `{}` can't be empty", location(key), key));
+        }
+    }
+    if string("host").is_some_and(|host| host.trim().is_empty()) {
+        problems.push(format!("{}: `host` can't be empty", location("host")));
+    }
+    if let Some(limit) = settings
+        .get("rate_limit")
+        .and_then(|value| value.as_integer())
+    {
+        if limit < 0 {
+            problems.push(format!(
+                "{}: `rate_limit` can't be negative, found {}",
+                location("rate_limit"),
+                limit
+            ));
+        }
+    }
+    for key in [
+        "posts_per_page",
+        "feed_limit",
+        "max_uri_length",
+        "max_request_size",
+    ] {
+        if let Some(count) = settings.get(key).and_then(|value| value.as_integer()) {
+            if count <= 0 {
+                problems.push(format!(
+                    "{}: `{}` must be greater than 0, found {}",
+                    location(key),
+                    key,
+                    count
+                ));
+            }
+        }
+    }
+    if let Some(policy) = string("trailing_slash") {
+        if !["always", "never"].contains(&policy) {
+            problems.push(format!(
+                "{}: `trailing_slash` must be \"always\" or \"never\", found \"{}\"",
+                location("trailing_slash"),
+                policy
+            ));
+        }
+    }
+    if let Some(pattern) = string("permalink") {
+        if !pattern.starts_with('/') || !pattern.contains(":slug") {
+            problems.push(format!(
+                "{}: `permalink` must start with / and contain :slug, found \"{}\"",
+                location("permalink"),
+                pattern
+            ));
+        }
+    }
+    if let Some(contact) = string("security_contact") {
+        if !contact.starts_with("mailto:") && !contact.starts_with("https://") {
+            problems.push(format!(
+                "{}: `security_contact` must be a mailto: or https:// URI, found \"{}\"",
+                location("security_contact"),
+                contact
+            ));
+        }
+    }
+    for key in [
+        "posts_dir",
+        "pages_dir",
+        "templates_dir",
+        "static_dir",
+        "output_dir",
+        "data_dir",
+        "themes_dir",
+    ] {
+        if string(key).is_some_and(|dir| dir.trim().is_empty()) {
+            problems.push(format!("{}: `{}` can't be empty", location(key), key));
+        }
+    }
+
+    if let Some(theme) = string("theme") {
+        let themes_dir = string("themes_dir").unwrap_or("themes");
+        if !Path::new(themes_dir).join(theme).is_dir() {
+            problems.push(format!(
+                "{}: theme \"{}\" not found in {}/",
+                location("theme"),
+                theme,
+                themes_dir
+            ));
+        }
+    }
+
+    let ignore_files = settings
+        .get("ignore_files")
+        .and_then(|value| value.as_array());
+    for pattern in ignore_files.into_iter().flatten() {
+        if pattern.as_str().is_none_or(str::is_empty) {
+            problems.push(format!(
+                "{}: `ignore_files` must hold file name patterns like \"*.swp\", found {}",
+                location("ignore_files"),
+                pattern
+            ));
+        }
+    }
+
+    let menu = settings.get("menu").and_then(|value| value.as_array());
+    for (index, item) in menu.into_iter().flatten().enumerate() {
+        let entry = format!("{}: menu entry {}", location("menu"), index + 1);
+        let Some(item) = item.as_table() else {
+            problems.push(format!("{} must be a table", entry));
+            continue;
+        };
+        for key in ["label", "url"] {
+            if !item.get(key).is_some_and(|value| value.is_str()) {
+                problems.push(format!("{} needs a `{}` string", entry, key));
+            }
+        }
+        if item.get("order").is_some_and(|value| !value.is_integer()) {
+            problems.push(format!("{}: `order` must be an integer", entry));
+        }
+        if item.get("external").is_some_and(|value| !value.is_bool()) {
+            problems.push(format!("{}: `external` must be true or false", entry));
+        }
+    }
+
+    let security_headers = settings
+        .get("security_headers")
+        .and_then(|value| value.as_table());
+    for (key, value) in security_headers.into_iter().flatten() {
+        let expected = match key.as_str() {
+            "enabled" => "boolean",
+            "content_security_policy"
+            | "x_content_type_options"
+            | "referrer_policy"
+            | "x_frame_options" => "string",
+            _ => {
+                eprintln!(
+                    "⚠️  {}: unknown setting `security_headers.{}`",
+                    location("security_headers"),
+                    key
+                );
+                continue;
+            }
+        };
+        if value.type_str() != expected {
+            problems.push(format!(
+                "{}: `security_headers.{}` must be {}, found {}",
+                location("security_headers"),
+                key,
+                with_article(expected),
+                with_article(value.type_str())
+            ));
+        }
+    }
+
+    let redirects = settings.get("redirects").and_then(|value| value.as_array());
+    for (index, item) in redirects.into_iter().flatten().enumerate() {
+        let entry = format!("{}: redirect {}", location("redirects"), index + 1);
+        let Some(item) = item.as_table() else {
+            problems.push(format!("{} must be a table", entry));
+            continue;
+        };
+        for key in ["from", "to"] {
+            if !item.get(key).is_some_and(|value| value.is_str()) {
+                problems.push(format!("{} needs a `{}` string", entry, key));
+            }
+        }
+        if let Some(from) = item.get("from").and_then(|value| value.as_str()) {
+            let path = from.trim().trim_matches('/');
+            if !from.starts_with('/') || path.is_empty() || path.split('/').any(|p| p == "..") {
+                problems.push(format!(
+                    "{}: `from` must be a site path like /old-url, found \"{}\"",
+                    entry, from
+                ));
+            }
+        }
+        if let Some(status) = item.get("status") {
+            let valid = status
+                .as_integer()
+                .is_some_and(|code| [301, 302, 303, 307, 308].contains(&code));
+            if !valid {
+                problems.push(format!(
+                    "{}: `status` must be 301, 302, 303, 307 or 308, found {}",
+                    entry, status
+                ));
+            }
+        }
+    }
+
+    let cache_rules = settings
+        .get("cache_rules")
+        .and_then(|value| value.as_array());
+    for (index, item) in cache_rules.into_iter().flatten().enumerate() {
+        let entry = format!("{}: cache rule {}", location("cache_rules"), index + 1);
+        let Some(item) = item.as_table() else {
+            problems.push(format!("{} must be a table", entry));
+            continue;
+        };
+        for key in ["path", "cache_control"] {
+            if !item.get(key).is_some_and(|value| value.is_str()) {
+                problems.push(format!("{} needs a `{}` string", entry, key));
+            }
+        }
+        if let Some(path) = item.get("path").and_then(|value| value.as_str()) {
+            if !path.starts_with('/') && !path.starts_with('*') {
+                problems.push(format!(
+                    "{}: `path` must be a site path pattern like /images/* or *.html, found \"{}\"",
+                    entry, path
+                ));
+            }
+        }
+    }
+
+    let content_types = settings
+        .get("content_types")
+        .and_then(|value| value.as_table());
+    for (extension, value) in content_types.into_iter().flatten() {
+        if extension.is_empty() || extension.contains(['.', '/']) {
+            problems.push(format!(
+                "{}: `content_types` keys must be file extensions like webmanifest, found \"{}\"",
+                location("content_types"),
+                extension
+            ));
+        }
+        if !value.as_str().is_some_and(|value| value.contains('/')) {
+            problems.push(format!(
+                "{}: `content_types.{}` must be a content type like \"audio/mp4\", found {}",
+                location("content_types"),
+                extension,
+                value
+            ));
+        }
+    }
+
+    problems
+}
+
+fn with_article(kind: &str) -> String {
+    match kind.chars().next() {
+        Some('a' | 'e' | 'i' | 'o' | 'u') => format!("an {}", kind),
+        _ => format!("a {}", kind),
+    }
+}
+
+/// Line number where the value of `key` in effect comes from: the top level or the active
+/// profile's table, whichever sets it last, or else the first `[[key]]` table
+fn line_of(contents: &str, key: &str, profile: &str) -> Option<usize> {
+    let array_header = format!("[[{}]]", key);
+    let profile_header = format!("[profiles.{}]", profile);
+    let mut section = "";
+    let mut found = None;
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line == array_header && found.is_none() {
+            found = Some(index + 1);
+        }
+        if line.starts_with('[') {
+            section = line;
+            continue;
+        }
+        let in_scope = section.is_empty() || section == profile_header;
+        let sets_key = line
+            .strip_prefix(key)
+            .is_some_and(|rest| rest.trim_start().starts_with('='));
+        if in_scope && sets_key {
+            found = Some(index + 1);
+        }
+    }
+    found
+}
+
+/// Copies `from` over `into`. Tables present in both are merged key by key, so a profile
+/// can override a single `[extra]` value
+fn merge(into: &mut toml::Table, from: toml::Table) {
+    for (key, value) in from {
+        match (into.get_mut(&key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(table)) => {
+                merge(existing, table)
+            }
+            (_, value) => {
+                into.insert(key, value);
+            }
+        }
+    }
+}
+
+/// What a profile turns on before the config file has its say. `preview` builds like
+/// `production` but keeps the site out of search engines and analytics, so staging copies
+/// can be deployed publicly. Profiles other than the built-in ones start from `dev`
+fn profile_defaults(profile: &str) -> toml::Table {
+    let production = profile == "production";
+    let preview = profile == "preview";
+    let published = production || preview;
+    let mut defaults = toml::Table::new();
+    defaults.insert("minify".to_string(), published.into());
+    defaults.insert("drafts".to_string(), (!published).into());
+    defaults.insert("future".to_string(), (!published).into());
+    defaults.insert("analytics".to_string(), production.into());
+    defaults.insert("noindex".to_string(), preview.into());
+    defaults.insert("robots_disallow".to_string(), preview.into());
+    defaults.insert("live_reload".to_string(), (!published).into());
+    defaults.insert("memory_cache".to_string(), (!published).into());
+    let mut security_headers = toml::Table::new();
+    security_headers.insert("enabled".to_string(), published.into());
+    defaults.insert("security_headers".to_string(), security_headers.into());
+    defaults
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Loads `contents` as the config file for `profile`
+    fn load_config(name: &str, contents: &str, profile: &str) -> io::Result<SiteConfig> {
+        let path =
+            std::env::temp_dir().join(format!("blog-config-{}-{}.toml", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        let config = load(&path, profile);
+        let _ = fs::remove_file(&path);
+        config
+    }
+
+    fn problems(name: &str, contents: &str, profile: &str) -> String {
+        match load_config(name, contents, profile) {
+            Ok(_) => panic!("{} was accepted", name),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn reports_every_problem_with_its_line() {
+        let contents = "title = \"Blog\"\n\
+            port = \"8000\"\n\
+            base_url = \"example.com\"\n\
+            tls_cert = \"cert.pem\"\n\
+            \n\
+            [profiles.dev]\n\
+            trailing_slash = \"sometimes\"\n\
+            admin_token = \" \"\n";
+        let message = problems("lines", contents, "dev");
+        assert!(message.starts_with("6 problems:"), "{}", message);
+        for problem in [
+            ".toml:2: `port` must be an integer, found a string",
+            ".toml:3: `base_url` must be an absolute URL like https://example.com",
+            ".toml:4: `tls_cert` and `tls_key` must be set together",
+            ".toml:4: `tls_cert` file cert.pem not found",
+            ".toml:7: `trailing_slash` must be \"always\" or \"never\", found \"sometimes\"",
+            ".toml:8: `admin_token` can't be empty",
+        ] {
+            assert!(message.contains(problem), "{} not in {}", problem, message);
+        }
+    }
+
+    #[test]
+    fn points_at_the_profile_line_that_set_a_value() {
+        let contents = "rate_limit = 10\n[profiles.production]\nrate_limit = -1\n";
+        let message = problems("profile-line", contents, "production");
+        assert!(message.contains(".toml:3: `rate_limit` can't be negative, found -1"));
+        // Other profiles don't see it
+        assert_eq!(
+            load_config("profile-line", contents, "dev")
+                .unwrap()
+                .server
+                .rate_limit,
+            10
+        );
+    }
+
+    #[test]
+    fn merges_profiles_over_the_defaults_and_top_level_settings() {
+        let contents = "title = \"Mine\"\n\
+            minify = true\n\
+            [security_headers]\n\
+            referrer_policy = \"no-referrer\"\n\
+            [profiles.dev]\n\
+            minify = false\n\
+            [profiles.production]\n\
+            analytics = false\n\
+            [profiles.production.security_headers]\n\
+            x_frame_options = \"DENY\"\n";
+        let dev = load_config("merge", contents, "dev").unwrap();
+        assert_eq!(dev.title, "Mine");
+        assert!(!dev.minify && dev.drafts && dev.server.live_reload);
+        assert!(!dev.server.security_headers.enabled);
+
+        let production = load_config("merge", contents, "production").unwrap();
+        assert_eq!(production.profile, "production");
+        assert!(production.minify && !production.drafts && !production.analytics);
+        // Tables are merged key by key
+        let headers = &production.server.security_headers;
+        assert!(headers.enabled);
+        assert_eq!(headers.referrer_policy, "no-referrer");
+        assert_eq!(headers.x_frame_options, "DENY");
+    }
+
+    #[test]
+    fn rejects_unknown_profiles() {
+        let message = problems("profile", "[profiles.staging]\nminify = true\n", "stage");
+        assert!(message.contains("unknown profile \"stage\""));
+        assert!(
+            load_config("profile", "[profiles.staging]\nminify = true\n", "staging")
+                .unwrap()
+                .minify
+        );
+    }
+
+    #[test]
+    fn star_matches_any_characters() {
+        assert!(glob_matches("*.swp", "post.md.swp"));
+        assert!(glob_matches("*.swp", ".swp"));
+        assert!(glob_matches("/images/*", "/images/a/b.png"));
+        assert!(glob_matches("#*#", "#post.md#"));
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("a*b*c", "abxbc"));
+        assert!(glob_matches("Thumbs.db", "Thumbs.db"));
+    }
+
+    #[test]
+    fn question_mark_matches_one_character() {
+        assert!(glob_matches("*.sw?", "post.md.swx"));
+        assert!(glob_matches("?.md", "é.md"));
+        assert!(!glob_matches("?.md", ".md"));
+        assert!(!glob_matches("?.md", "ab.md"));
+    }
+
+    #[test]
+    fn wildcards_match_a_leading_dot() {
+        assert!(glob_matches("*.swp", ".post.md.swp"));
+        assert!(glob_matches(".#*", ".#post.md"));
+        assert!(glob_matches("?DS_Store", ".DS_Store"));
+    }
+
+    #[test]
+    fn rejects_non_matching_names() {
+        assert!(!glob_matches("*.swp", "post.md"));
+        assert!(!glob_matches("*.swp", "post.swp.md"));
+        assert!(!glob_matches("*~", "post~.md"));
+        assert!(!glob_matches("#*#", "#post.md"));
+        assert!(!glob_matches(".#*", "post.#md"));
+        assert!(!glob_matches("/images/*", "/posts/images/a.png"));
+        assert!(!glob_matches("Thumbs.db", "thumbs.db"));
+        assert!(!glob_matches("", "post.md"));
+    }
+
+    #[test]
+    fn ignores_by_file_name() {
+        let patterns = ServerSettings::default().ignore_files;
+        assert!(is_ignored(
+            &patterns,
+            Path::new("content/posts/.hello.md.swp")
+        ));
+        assert!(is_ignored(&patterns, Path::new("static/.DS_Store")));
+        assert!(!is_ignored(&patterns, Path::new("content/posts/hello.md")));
+        assert!(!is_ignored(
+            &patterns,
+            Path::new("content/.#drafts/hello.md")
+        ));
+    }
+}
@@ -0,0 +1,66 @@
+use crate::report;
+use std::fs;
+use std::path::Path;
+
+/// Reads every JSON, TOML and YAML file in `dir` into one object keyed by file name
+/// without extension, so `data/projects.yaml` becomes `data.projects` in templates.
+/// Subdirectories become nested objects. Files that can't be read or parsed are reported
+/// and left out
+pub fn load_data(dir: &Path) -> serde_json::Map<String, serde_json::Value> {
+    let mut data = serde_json::Map::new();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return data;
+    };
+
+    let mut paths: Vec<_> = entries.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+    paths.sort();
+    for path in paths {
+        let name = if path.is_dir() {
+            path.file_name()
+        } else {
+            path.file_stem()
+        };
+        let Some(name) = name.and_then(|s| s.to_str()) else {
+            continue;
+        };
+        if path.is_dir() {
+            data.insert(name.to_string(), load_data(&path).into());
+            continue;
+        }
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default();
+        if !matches!(extension, "json" | "toml" | "yaml" | "yml") {
+            continue;
+        }
+
+        let parsed = fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|content| parse(extension, &content));
+        match parsed {
+            Ok(value) => {
+                if data.insert(name.to_string(), value).is_some() {
+                    report::warn(format!(
+                        "{}: another data file is also named \"{}\"",
+                        path.display(),
+                        name
+                    ));
+                }
+            }
+            Err(e) => report::error(format!("{}: {}", path.display(), e)),
+        }
+    }
+
+    data
+}
+
+fn parse(extension: &str, content: &str) -> Result<serde_json::Value, String> {
+    match extension {
+        "json" => serde_json::from_str(content).map_err(|e| e.to_string()),
+        "toml" => toml::from_str::<toml::Value>(content)
+            .map_err(|e| e.to_string())
+            .and_then(|value| serde_json::to_value(value).map_err(|e| e.to_string())),
+        _ => serde_yaml::from_str(content).map_err(|e| e.to_string()),
+    }
+}
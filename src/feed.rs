@@ -0,0 +1,242 @@
+use crate::config::SiteConfig;
+use crate::generator::{absolute_url, escape_html, parse_date, BuildOptions, Post};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+
+/// A post as it appears in every feed format
+pub struct FeedItem {
+    pub title: String,
+    /// Absolute URL of the post, also used as its unique ID
+    pub url: String,
+    pub summary: String,
+    /// Full post body, only set when feeds carry full content
+    pub content_html: Option<String>,
+    pub image: Option<String>,
+    pub tags: Vec<String>,
+    pub published: Option<DateTime<Local>>,
+    pub updated: Option<DateTime<Local>>,
+}
+
+/// Builds feed items for the most recent posts; `posts` must already be sorted newest first
+pub fn feed_items(options: &BuildOptions, posts: &[&Post]) -> Vec<FeedItem> {
+    posts
+        .iter()
+        .take(options.feed_limit)
+        .map(|post| {
+            let published = parse_date(&post.date).and_then(to_local);
+            FeedItem {
+                title: post.title.clone(),
+                url: absolute_url(&options.base_url, &post.path),
+                summary: post.excerpt.clone(),
+                content_html: options.feed_full_content.then(|| post.html_content.clone()),
+                image: post
+                    .cover
+                    .as_ref()
+                    .map(|cover| absolute_url(&options.base_url, cover)),
+                tags: post.tags.clone(),
+                published,
+                updated: parse_date(&post.updated).and_then(to_local).or(published),
+            }
+        })
+        .collect()
+}
+
+/// RSS 2.0 feed
+pub fn generate_rss(site: &SiteConfig, base_url: &str, items: &[FeedItem]) -> String {
+    rss_channel(
+        site,
+        &site.title,
+        base_url,
+        &format!("{}/rss.xml", base_url),
+        items,
+    )
+}
+
+/// RSS 2.0 feed of the posts with one tag, published at `feed_path`
+pub fn generate_tag_rss(
+    site: &SiteConfig,
+    base_url: &str,
+    tag: &str,
+    feed_path: &str,
+    items: &[FeedItem],
+) -> String {
+    let title = format!("{} · #{}", site.title, tag);
+    rss_channel(
+        site,
+        &title,
+        base_url,
+        &absolute_url(base_url, feed_path),
+        items,
+    )
+}
+
+fn rss_channel(
+    site: &SiteConfig,
+    title: &str,
+    base_url: &str,
+    self_url: &str,
+    items: &[FeedItem],
+) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\">\n");
+    xml.push_str("<channel>\n");
+    xml.push_str(&format!("  <title>{}</title>\n", escape_html(title)));
+    xml.push_str(&format!("  <link>{}/</link>\n", escape_html(base_url)));
+    xml.push_str(&format!(
+        "  <description>{}</description>\n",
+        escape_html(&site.description)
+    ));
+    xml.push_str(&format!(
+        "  <atom:link href=\"{}\" rel=\"self\" type=\"application/rss+xml\" />\n",
+        escape_html(self_url)
+    ));
+    xml.push_str(&format!(
+        "  <lastBuildDate>{}</lastBuildDate>\n",
+        Local::now().to_rfc2822()
+    ));
+
+    for item in items {
+        xml.push_str("  <item>\n");
+        xml.push_str(&format!(
+            "    <title>{}</title>\n",
+            escape_html(&item.title)
+        ));
+        xml.push_str(&format!("    <link>{}</link>\n", escape_html(&item.url)));
+        xml.push_str(&format!(
+            "    <guid isPermaLink=\"true\">{}</guid>\n",
+            escape_html(&item.url)
+        ));
+        if let Some(published) = item.published {
+            xml.push_str(&format!(
+                "    <pubDate>{}</pubDate>\n",
+                published.to_rfc2822()
+            ));
+        }
+        for tag in &item.tags {
+            xml.push_str(&format!("    <category>{}</category>\n", escape_html(tag)));
+        }
+        xml.push_str(&format!(
+            "    <description>{}</description>\n",
+            escape_html(item.content_html.as_ref().unwrap_or(&item.summary))
+        ));
+        xml.push_str("  </item>\n");
+    }
+
+    xml.push_str("</channel>\n</rss>\n");
+    xml
+}
+
+/// Atom feed
+pub fn generate_atom(site: &SiteConfig, base_url: &str, items: &[FeedItem]) -> String {
+    // The feed was last updated whenever its most recently updated entry was
+    let feed_updated = items
+        .iter()
+        .filter_map(|item| item.updated)
+        .max()
+        .unwrap_or_else(Local::now);
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    xml.push_str(&format!("  <title>{}</title>\n", escape_html(&site.title)));
+    xml.push_str(&format!(
+        "  <subtitle>{}</subtitle>\n",
+        escape_html(&site.description)
+    ));
+    xml.push_str(&format!("  <id>{}/</id>\n", escape_html(base_url)));
+    xml.push_str(&format!("  <link href=\"{}/\" />\n", escape_html(base_url)));
+    xml.push_str(&format!(
+        "  <link href=\"{}/atom.xml\" rel=\"self\" type=\"application/atom+xml\" />\n",
+        escape_html(base_url)
+    ));
+    xml.push_str(&format!(
+        "  <updated>{}</updated>\n",
+        feed_updated.to_rfc3339()
+    ));
+    xml.push_str(&format!(
+        "  <author><name>{}</name></author>\n",
+        escape_html(site.author.as_ref().unwrap_or(&site.title))
+    ));
+
+    for item in items {
+        xml.push_str("  <entry>\n");
+        xml.push_str(&format!(
+            "    <title>{}</title>\n",
+            escape_html(&item.title)
+        ));
+        xml.push_str(&format!("    <id>{}</id>\n", escape_html(&item.url)));
+        xml.push_str(&format!(
+            "    <link href=\"{}\" />\n",
+            escape_html(&item.url)
+        ));
+        if let Some(published) = item.published {
+            xml.push_str(&format!(
+                "    <published>{}</published>\n",
+                published.to_rfc3339()
+            ));
+        }
+        xml.push_str(&format!(
+            "    <updated>{}</updated>\n",
+            item.updated.unwrap_or(feed_updated).to_rfc3339()
+        ));
+        for tag in &item.tags {
+            xml.push_str(&format!("    <category term=\"{}\" />\n", escape_html(tag)));
+        }
+        xml.push_str(&format!(
+            "    <summary>{}</summary>\n",
+            escape_html(&item.summary)
+        ));
+        if let Some(content) = &item.content_html {
+            xml.push_str(&format!(
+                "    <content type=\"html\">{}</content>\n",
+                escape_html(content)
+            ));
+        }
+        xml.push_str("  </entry>\n");
+    }
+
+    xml.push_str("</feed>\n");
+    xml
+}
+
+/// JSON Feed 1.1 (https://www.jsonfeed.org/version/1.1/)
+pub fn generate_json_feed(site: &SiteConfig, base_url: &str, items: &[FeedItem]) -> String {
+    let items_data: Vec<_> = items
+        .iter()
+        .map(|item| {
+            let mut data = serde_json::json!({
+                "id": item.url,
+                "url": item.url,
+                "title": item.title,
+                "summary": item.summary,
+                "content_html": item.content_html.as_ref().unwrap_or(&item.summary),
+                "tags": item.tags,
+            });
+            if let Some(image) = &item.image {
+                data["image"] = image.as_str().into();
+            }
+            if let Some(published) = item.published {
+                data["date_published"] = published.to_rfc3339().into();
+            }
+            if let Some(updated) = item.updated {
+                data["date_modified"] = updated.to_rfc3339().into();
+            }
+            data
+        })
+        .collect();
+
+    let feed = serde_json::json!({
+        "version": "https://jsonfeed.org/version/1.1",
+        "title": site.title,
+        "description": site.description,
+        "home_page_url": format!("{}/", base_url),
+        "feed_url": format!("{}/feed.json", base_url),
+        "authors": [{ "name": site.author.as_ref().unwrap_or(&site.title) }],
+        "items": items_data,
+    });
+
+    serde_json::to_string_pretty(&feed).unwrap_or_default()
+}
+
+/// Frontmatter dates are written in the author's local time
+fn to_local(date: NaiveDateTime) -> Option<DateTime<Local>> {
+    Local.from_local_datetime(&date).earliest()
+}
@@ -0,0 +1,78 @@
+use crate::generator::{markdown_to_html, parse_date, process_inline_markdown, slugify};
+use std::collections::HashMap;
+use tera::{try_get_value, Filter, Tera, Value};
+
+/// Registers the site's own template filters. `date` and `slugify` replace Tera's built-in
+/// ones so they accept the same dates as frontmatter and produce the same slugs as tag URLs
+pub fn register(tera: &mut Tera) {
+    tera.register_filter("date", date);
+    tera.register_filter("slugify", slugify_filter);
+    tera.register_filter("truncate_words", truncate_words);
+    tera.register_filter("markdown", Markdown);
+}
+
+/// `{{ post.date | date(format="%B %-d, %Y") }}`, with a chrono format defaulting to
+/// `%Y-%m-%d`
+fn date(value: &Value, args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let text = try_get_value!("date", "value", String, value);
+    let format = match args.get("format") {
+        Some(format) => try_get_value!("date", "format", String, format),
+        None => "%Y-%m-%d".to_string(),
+    };
+    let date = parse_date(&text)
+        .ok_or_else(|| tera::Error::msg(format!("Filter `date` can't parse \"{}\"", text)))?;
+    Ok(Value::String(date.format(&format).to_string()))
+}
+
+/// `{{ tag | slugify }}`
+fn slugify_filter(value: &Value, _: &HashMap<String, Value>) -> tera::Result<Value> {
+    let text = try_get_value!("slugify", "value", String, value);
+    Ok(Value::String(slugify(&text)))
+}
+
+/// `{{ excerpt | truncate_words(count=30, end="…") }}` keeps the first `count` words and
+/// appends `end` (default `…`) if any were cut
+fn truncate_words(value: &Value, args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let text = try_get_value!("truncate_words", "value", String, value);
+    let count = match args.get("count") {
+        Some(count) => try_get_value!("truncate_words", "count", usize, count),
+        None => return Err(tera::Error::msg("Filter `truncate_words` needs a `count`")),
+    };
+    let end = match args.get("end") {
+        Some(end) => try_get_value!("truncate_words", "end", String, end),
+        None => "…".to_string(),
+    };
+
+    let words: Vec<&str> = text.split_whitespace().collect();
+    if words.len() <= count {
+        return Ok(Value::String(text));
+    }
+    Ok(Value::String(format!(
+        "{}{}",
+        words[..count].join(" "),
+        end
+    )))
+}
+
+/// `{{ site.extra.footer_text | markdown }}` renders markdown to HTML, without escaping.
+/// With `inline=true` only links, images and emphasis are rendered, without wrapping `<p>`
+struct Markdown;
+
+impl Filter for Markdown {
+    fn filter(&self, value: &Value, args: &HashMap<String, Value>) -> tera::Result<Value> {
+        let text = try_get_value!("markdown", "value", String, value);
+        let inline = match args.get("inline") {
+            Some(inline) => try_get_value!("markdown", "inline", bool, inline),
+            None => false,
+        };
+        Ok(Value::String(if inline {
+            process_inline_markdown(&text)
+        } else {
+            markdown_to_html(&text)
+        }))
+    }
+
+    fn is_safe(&self) -> bool {
+        true
+    }
+}
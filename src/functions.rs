@@ -0,0 +1,101 @@
+use crate::generator::{slugify, tag_feed_path, Post};
+use std::collections::{BTreeMap, HashMap};
+use tera::{from_value, Tera, Value};
+
+/// A post as template functions return it: every field but its content, which would make
+/// each post page depend on the body of every other post
+pub fn post_data(post: &Post) -> Value {
+    let mut data = serde_json::to_value(post).unwrap_or_default();
+    if let Some(fields) = data.as_object_mut() {
+        fields.remove("content");
+    }
+    data
+}
+
+/// Registers functions that let templates query the site's posts (newest first), so e.g.
+/// a footer can list recent posts without its own variable:
+///
+/// - `get_posts(tag=, section=, lang=, limit=)`, every argument optional
+/// - `get_post(slug=, lang=)`, failing the render if there's no such post
+/// - `get_tags()`, each tag with its `name`, `slug`, `count` and `feed_url`, most used first
+pub fn register(tera: &mut Tera, posts: Vec<Value>) {
+    let all = posts.clone();
+    tera.register_function("get_posts", move |args: &HashMap<String, Value>| {
+        let tag = optional::<String>(args, "tag")?;
+        let section = optional::<String>(args, "section")?;
+        let lang = optional::<String>(args, "lang")?;
+        let limit = optional::<usize>(args, "limit")?.unwrap_or(usize::MAX);
+        let matching: Vec<Value> = all
+            .iter()
+            .filter(|post| {
+                tag.as_ref().is_none_or(|tag| {
+                    post["tags"]
+                        .as_array()
+                        .is_some_and(|tags| tags.iter().any(|t| t == tag.as_str()))
+                })
+            })
+            .filter(|post| {
+                section
+                    .as_ref()
+                    .is_none_or(|s| post["section"] == s.as_str())
+            })
+            .filter(|post| lang.as_ref().is_none_or(|l| post["lang"] == l.as_str()))
+            .take(limit)
+            .cloned()
+            .collect();
+        Ok(Value::Array(matching))
+    });
+
+    let all = posts.clone();
+    tera.register_function("get_post", move |args: &HashMap<String, Value>| {
+        let slug = optional::<String>(args, "slug")?
+            .ok_or_else(|| tera::Error::msg("Function `get_post` needs a `slug`"))?;
+        let lang = optional::<String>(args, "lang")?;
+        all.iter()
+            .find(|post| {
+                post["slug"] == slug.as_str()
+                    && lang.as_ref().is_none_or(|l| post["lang"] == l.as_str())
+            })
+            .cloned()
+            .ok_or_else(|| tera::Error::msg(format!("Function `get_post`: no post \"{}\"", slug)))
+    });
+
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    for tag in posts
+        .iter()
+        .filter_map(|post| post["tags"].as_array())
+        .flatten()
+        .filter_map(Value::as_str)
+    {
+        *counts.entry(tag.to_string()).or_default() += 1;
+    }
+    let mut tags: Vec<(String, usize)> = counts.into_iter().collect();
+    tags.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let tags: Vec<Value> = tags
+        .into_iter()
+        .map(|(name, count)| {
+            serde_json::json!({
+                "name": name,
+                "slug": slugify(&name),
+                "count": count,
+                "feed_url": tag_feed_path(&name),
+            })
+        })
+        .collect();
+    tera.register_function("get_tags", move |_: &HashMap<String, Value>| {
+        Ok(Value::Array(tags.clone()))
+    });
+}
+
+fn optional<T: serde::de::DeserializeOwned>(
+    args: &HashMap<String, Value>,
+    name: &str,
+) -> tera::Result<Option<T>> {
+    args.get(name)
+        .map(|value| {
+            from_value(value.clone()).map_err(|_| {
+                tera::Error::msg(format!("Argument `{}` has the wrong type: {}", name, value))
+            })
+        })
+        .transpose()
+}
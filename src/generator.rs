@@ -1,107 +1,1232 @@
+use crate::config::{is_ignored, MenuItem, SiteConfig};
+use crate::images::{self, ImageVariants};
+use crate::report::{self, BuildReport};
+use crate::{compress, data, feed, filters, functions, links, minify, shortcodes};
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveDateTime};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
-use std::path::Path;
-use std::sync::{Arc, Mutex, OnceLock};
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::SystemTime;
 use tera::{Context, Tera};
 
 #[derive(Clone)]
+pub struct BuildOptions {
+    /// Include posts dated in the future (for previewing scheduled posts)
+    pub include_future: bool,
+    /// Build preview pages for posts marked as drafts
+    pub drafts: bool,
+    /// Absolute URL the site is served from, used for links in the sitemap and feeds
+    pub base_url: String,
+    /// Number of most recent posts included in feeds
+    pub feed_limit: usize,
+    /// Put the full post body in feeds instead of the excerpt
+    pub feed_full_content: bool,
+    /// Number of posts listed per index page
+    pub posts_per_page: usize,
+    /// Minify generated HTML before writing it (production builds)
+    pub minify: bool,
+    /// Write WebP copies of JPEG and PNG images and serve them through `<picture>`
+    pub webp: bool,
+    /// URL pattern for posts, e.g. `/:year/:month/:slug/`. Supports `:year`, `:month`,
+    /// `:day`, `:section` and `:slug`; a pattern ending in `/` is written as a directory index
+    pub permalink: String,
+    /// Fail the build if any post or page couldn't be parsed or rendered, instead of
+    /// skipping it
+    pub strict: bool,
+    /// Fail the build on broken internal links instead of only warning about them
+    pub strict_links: bool,
+    /// Save build statistics to `output/build-report.json`
+    pub build_report: bool,
+    /// Write gzip and brotli copies of text files next to them
+    pub precompress: bool,
+    /// Site settings from `config.toml`, available to templates as `site`
+    pub site: SiteConfig,
+}
+
+pub struct BuildSummary {
+    /// Publish time of the earliest post held back because it is dated in the future
+    pub next_scheduled: Option<NaiveDateTime>,
+    /// Number of posts published
+    pub posts: usize,
+}
+
+/// A parsed post. Templates get it whole, with the rendered body as `content`
+#[derive(Clone, Serialize)]
 pub struct Post {
+    #[serde(skip)]
+    pub source: PathBuf,
     pub title: String,
     pub slug: String,
+    /// Site path of the post's page, from the permalink pattern (e.g. `/2024/01/hello/`)
+    pub path: String,
+    /// Subdirectory of `posts/` the post was found in, e.g. `rust` for `posts/rust/intro.md`
+    pub section: Option<String>,
+    /// Directory of a page bundle (`posts/my-post/index.md`), whose other files are copied
+    /// next to the rendered post
+    #[serde(skip)]
+    pub bundle: Option<PathBuf>,
     pub date: String,
+    pub updated: String,
     pub excerpt: String,
+    /// Meta description for search engines, separate from the visual excerpt
+    pub description: Option<String>,
+    pub keywords: Vec<String>,
+    pub canonical_url: Option<String>,
+    /// Language tag of the post, e.g. `en` or `id`
+    pub lang: String,
+    pub cover: Option<String>,
+    /// Pinned to the featured section at the top of the index
+    pub featured: bool,
+    /// Unfinished, only built when the profile includes drafts
+    pub draft: bool,
+    pub tags: Vec<String>,
+    /// Old URL paths that should redirect to this post
+    pub aliases: Vec<String>,
+    pub series: Option<String>,
+    pub series_part: Option<u32>,
+    /// Frontmatter keys not known to the generator, exposed to templates as `extra`
+    pub extra: BTreeMap<String, String>,
+    pub word_count: usize,
+    /// Estimated reading time in minutes
+    pub reading_time: usize,
+    #[serde(rename = "content")]
     pub html_content: String,
 }
 
-// Global Tera instance that persists across builds
-static TERA_INSTANCE: OnceLock<Arc<Mutex<Tera>>> = OnceLock::new();
+/// A standalone page from `pages/` (About, Contact, ...), kept out of the index and feeds
+#[derive(Clone)]
+pub struct Page {
+    pub source: PathBuf,
+    pub title: String,
+    pub slug: String,
+    /// Position in the navigation menu, lower first
+    pub order: i32,
+    pub extra: BTreeMap<String, String>,
+    pub html_content: String,
+}
 
-fn get_tera() -> Arc<Mutex<Tera>> {
-    TERA_INSTANCE
-        .get_or_init(|| {
-            let mut tera = Tera::default();
+/// All published parts of a series, in reading order
+struct Series<'a> {
+    name: String,
+    slug: String,
+    parts: Vec<&'a Post>,
+}
 
-            // Load templates manually
-            let post_template =
-                fs::read_to_string("templates/post.html").expect("Failed to read post.html");
-            let index_template =
-                fs::read_to_string("templates/index.html").expect("Failed to read index.html");
-            let base_css =
-                fs::read_to_string("templates/base.css").expect("Failed to read base.css");
+// Parsed posts from earlier builds, reused while the source file's mtime is unchanged
+static POST_CACHE: OnceLock<Mutex<HashMap<PathBuf, (SystemTime, Post)>>> = OnceLock::new();
+
+// Hash of the context each post page was last rendered with, keyed by output path
+static RENDER_CACHE: OnceLock<Mutex<HashMap<String, u64>>> = OnceLock::new();
+
+// Whether directory-style URLs end with a slash, from the `trailing_slash` setting
+static TRAILING_SLASH: AtomicBool = AtomicBool::new(true);
+
+// Files written by the current build, checked against the previous build's manifest to
+// remove output that no longer has a source
+static OUTPUTS: OnceLock<Mutex<HashSet<PathBuf>>> = OnceLock::new();
+
+// List of files generated by the last build, kept in the output directory
+const OUTPUT_MANIFEST: &str = ".manifest";
+
+const BUILD_REPORT: &str = "build-report.json";
+
+// Average reading speed used for the reading time estimate
+const WORDS_PER_MINUTE: usize = 200;
+
+// Language assumed for posts without a `lang` field
+const DEFAULT_LANG: &str = "en";
+
+// Templates every site needs, built into the binary so a site without `templates/` (or
+// missing some of them) still builds. Files of the same name in `templates/` or the theme
+// replace these, and any other file there is loaded too, for use with `{% extends %}`,
+// `{% include %}` and `{% import %}`
+const DEFAULT_TEMPLATES: &[(&str, &str)] = &[
+    ("post.html", include_str!("../templates/post.html")),
+    ("index.html", include_str!("../templates/index.html")),
+    ("series.html", include_str!("../templates/series.html")),
+    ("page.html", include_str!("../templates/page.html")),
+    ("404.html", include_str!("../templates/404.html")),
+    ("search.html", include_str!("../templates/search.html")),
+    ("archive.html", include_str!("../templates/archive.html")),
+    ("list.html", include_str!("../templates/list.html")),
+    ("base.css", include_str!("../templates/base.css")),
+    (
+        "shortcodes/youtube.html",
+        include_str!("../templates/shortcodes/youtube.html"),
+    ),
+];
+
+// Number of "You might also like" posts shown under each article
+const RELATED_POSTS: usize = 3;
+
+// Global Tera instance that persists across builds. Rendering only needs read access,
+// so post pages can be rendered from many threads at once
+static TERA_INSTANCE: OnceLock<Arc<RwLock<Tera>>> = OnceLock::new();
+
+fn get_tera(template_dirs: &[PathBuf]) -> io::Result<Arc<RwLock<Tera>>> {
+    if let Some(tera) = TERA_INSTANCE.get() {
+        return Ok(tera.clone());
+    }
+    let tera = Arc::new(RwLock::new(load_templates(template_dirs)?));
+    Ok(TERA_INSTANCE.get_or_init(|| tera).clone())
+}
 
-            tera.add_raw_template("post.html", &post_template)
-                .expect("Failed to add post template");
-            tera.add_raw_template("index.html", &index_template)
-                .expect("Failed to add index template");
-            tera.add_raw_template("base.css", &base_css)
-                .expect("Failed to add CSS template");
+fn load_templates(template_dirs: &[PathBuf]) -> io::Result<Tera> {
+    let invalid = |e: tera::Error| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Failed to load templates: {}", e),
+        )
+    };
+
+    // Every file under each directory, named by its path relative to it (e.g.
+    // `partials/header.html`). Earlier directories win, and inheritance is only resolved
+    // once all are loaded so site templates can extend theme ones
+    let mut tera = Tera::default();
+    for (index, dir) in template_dirs.iter().enumerate() {
+        let parsed = Tera::parse(&format!("{}/**/*", dir.display())).map_err(invalid)?;
+        if index == 0 {
+            tera = parsed;
+        } else {
+            tera.extend(&parsed).map_err(invalid)?;
+        }
+    }
+    let mut defaults = Tera::default();
+    defaults
+        .add_raw_templates(DEFAULT_TEMPLATES.iter().copied())
+        .map_err(invalid)?;
+    tera.extend(&defaults).map_err(invalid)?;
+    filters::register(&mut tera);
+    Ok(tera)
+}
 
-            Arc::new(Mutex::new(tera))
-        })
-        .clone()
+/// Loads the templates again for the next build, e.g. after one was edited. On error the
+/// previous templates stay in use
+pub fn reload_templates(site: &SiteConfig) -> io::Result<()> {
+    let tera = load_templates(&template_dirs(site))?;
+    match TERA_INSTANCE.get() {
+        Some(instance) => *instance.write().unwrap() = tera,
+        None => return Ok(()),
+    }
+    // Unchanged contexts no longer mean unchanged pages
+    RENDER_CACHE
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap()
+        .clear();
+    Ok(())
 }
 
-pub fn build_blog() -> std::io::Result<()> {
-    fs::create_dir_all("output")?;
+/// Latest modification time of any file in the template directories
+pub fn templates_modified(site: &SiteConfig) -> Option<SystemTime> {
+    fn latest(dir: &Path) -> Option<SystemTime> {
+        fs::read_dir(dir)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| match entry.file_type() {
+                Ok(kind) if kind.is_dir() => latest(&entry.path()),
+                _ => entry.metadata().and_then(|m| m.modified()).ok(),
+            })
+            .max()
+    }
+    template_dirs(site)
+        .iter()
+        .filter_map(|dir| latest(dir))
+        .max()
+}
 
-    let posts_dir = "posts";
+/// Directories templates are looked up in: the site's own first, so its files override
+/// the theme's
+pub fn template_dirs(site: &SiteConfig) -> Vec<PathBuf> {
+    let mut dirs = vec![PathBuf::from(&site.server.templates_dir)];
+    if let Some(theme) = &site.server.theme {
+        dirs.push(
+            Path::new(&site.server.themes_dir)
+                .join(theme)
+                .join("templates"),
+        );
+    }
+    dirs
+}
+
+/// Directories of files copied as-is to the site root. The theme's go first so the site's
+/// own static files replace them
+pub fn static_dirs(site: &SiteConfig) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(theme) = &site.server.theme {
+        dirs.push(
+            Path::new(&site.server.themes_dir)
+                .join(theme)
+                .join("static"),
+        );
+    }
+    dirs.push(PathBuf::from(&site.static_dir));
+    dirs
+}
+
+pub fn build_blog(options: &BuildOptions) -> std::io::Result<BuildSummary> {
+    let mut report = BuildReport::start();
+    TRAILING_SLASH.store(
+        options.site.server.trailing_slash != "never",
+        Ordering::Relaxed,
+    );
+    let out = options.site.server.output_dir.as_str();
+    fs::create_dir_all(out)?;
+    let theme_dir = options
+        .site
+        .server
+        .theme
+        .as_ref()
+        .map(|theme| Path::new(&options.site.server.themes_dir).join(theme));
+    if let Some(dir) = theme_dir.as_ref().filter(|dir| !dir.is_dir()) {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("theme directory {} not found", dir.display()),
+        ));
+    }
+    OUTPUTS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap()
+        .clear();
+
+    let posts_dir = options.site.server.posts_dir.as_str();
     let mut posts = Vec::new();
+    let mut post_cache = POST_CACHE.get_or_init(Default::default).lock().unwrap();
 
     if Path::new(posts_dir).exists() {
-        for entry in fs::read_dir(posts_dir)? {
-            let entry = entry?;
-            let path = entry.path();
-
-            if path.extension().and_then(|s| s.to_str()) == Some("md") {
-                if let Ok(content) = fs::read_to_string(&path) {
-                    if let Some(post) = parse_post(&path, &content) {
-                        posts.push(post);
+        for path in markdown_files(Path::new(posts_dir), &options.site.server.ignore_files)? {
+            // Reuse the parsed post from an earlier build while the file is unchanged
+            let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+            if let Some((cached_at, post)) = post_cache.get(&path) {
+                if Some(*cached_at) == modified {
+                    posts.push(post.clone());
+                    continue;
+                }
+            }
+
+            // Problems with a single post are collected so one bad file doesn't hide the rest
+            let content = match fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(e) => {
+                    report::error(format!("{}: could not read file: {}", path.display(), e));
+                    continue;
+                }
+            };
+            match parse_post(&path, &content) {
+                Ok(Some(mut post)) => {
+                    // A directory with an `index.md` is a page bundle: the post plus its assets
+                    let bundle = path.parent().filter(|dir| {
+                        path.file_name() == Some("index.md".as_ref())
+                            && *dir != Path::new(posts_dir)
+                    });
+                    post.bundle = bundle.map(Path::to_path_buf);
+
+                    // Posts in subdirectories belong to a section named after the directory
+                    post.section = path
+                        .parent()
+                        .and_then(|dir| {
+                            if bundle.is_some() {
+                                dir.parent()
+                            } else {
+                                Some(dir)
+                            }
+                        })
+                        .and_then(|dir| dir.strip_prefix(posts_dir).ok())
+                        .map(|dir| dir.to_string_lossy().replace('\\', "/"))
+                        .filter(|dir| !dir.is_empty());
+                    if let Some(modified) = modified {
+                        post_cache.insert(path.clone(), (modified, post.clone()));
+                    }
+                    posts.push(post);
+                }
+                Ok(None) => report::error(format!(
+                    "{}: missing frontmatter, expected a block between `---` lines at the top",
+                    path.display()
+                )),
+                Err(e) => report::error(e.to_string()),
+            }
+        }
+    }
+    post_cache.retain(|path, _| posts.iter().any(|p| &p.source == path));
+    drop(post_cache);
+
+    let pages_dir = options.site.pages_dir.as_str();
+    let mut pages = Vec::new();
+
+    if Path::new(pages_dir).exists() {
+        for entry in fs::read_dir(pages_dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|s| s.to_str()) == Some("md")
+                && !is_ignored(&options.site.server.ignore_files, &path)
+            {
+                let content = match fs::read_to_string(&path) {
+                    Ok(content) => content,
+                    Err(e) => {
+                        report::error(format!("{}: could not read file: {}", path.display(), e));
+                        continue;
                     }
+                };
+                match parse_page(&path, &content) {
+                    Ok(Some(page)) => pages.push(page),
+                    Ok(None) => report::error(format!(
+                        "{}: missing frontmatter, expected a block between `---` lines at the top",
+                        path.display()
+                    )),
+                    Err(e) => report::error(e.to_string()),
                 }
             }
         }
     }
+    pages.sort_by(|a, b| (a.order, &a.title).cmp(&(b.order, &b.title)));
+
+    // Without the slug every post would be written to the same path
+    if !options.permalink.contains(":slug") {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "permalink pattern \"{}\" must contain :slug",
+                options.permalink
+            ),
+        ));
+    }
+    for post in &mut posts {
+        post.path = permalink(&options.permalink, post);
+    }
+
+    // Drafts only get a preview page at `/drafts/<slug>/`, and stay out of listings, feeds,
+    // the sitemap and everything else that lists posts
+    let (mut drafts, published): (Vec<Post>, Vec<Post>) =
+        posts.into_iter().partition(|post| post.draft);
+    posts = published;
+    if !options.drafts {
+        drafts.clear();
+    }
+    for draft in &mut drafts {
+        draft.path = canonical_path(&format!(
+            "{}/drafts/{}/",
+            lang_prefix(&draft.lang),
+            draft.slug
+        ));
+    }
+
+    // Every post and page needs its own output file. Translations share a slug but
+    // live under their language's prefix
+    let mut paths: HashMap<String, &Path> = HashMap::new();
+    let sources = posts
+        .iter()
+        .chain(&drafts)
+        .map(|p| (p.path.clone(), &p.source))
+        .chain(pages.iter().map(|p| (page_path(p), &p.source)));
+    for (path, source) in sources {
+        if let Some(other) = paths.insert(path.clone(), source) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "path \"{}\" is used by both {} and {}",
+                    path,
+                    other.display(),
+                    source.display()
+                ),
+            ));
+        }
+    }
+
+    // Hold back scheduled posts until their publish time
+    let mut next_scheduled: Option<NaiveDateTime> = None;
+    if !options.include_future {
+        let now = Local::now().naive_local();
+        posts.retain(|post| match parse_date(&post.date) {
+            Some(publish_at) if publish_at > now => {
+                println!("⏳ Scheduled: {} ({})", post.slug, publish_at);
+                next_scheduled =
+                    Some(next_scheduled.map_or(publish_at, |next| publish_at.min(next)));
+                false
+            }
+            _ => true,
+        });
+    }
 
     posts.sort_by(|a, b| b.date.cmp(&a.date));
+    report.posts = posts.len();
+    report.pages = pages.len();
+    report.stage("load");
+
+    // Copy images along with their resized variants
+    let images = images::process_images(
+        &Path::new(posts_dir).join("images"),
+        &Path::new(out).join("images"),
+        options.webp,
+        &options.site.server.ignore_files,
+    )?;
+
+    // Everything under static/ is copied as-is to the site root
+    for static_dir in static_dirs(&options.site).iter().filter(|dir| dir.exists()) {
+        let copied = copy_dir(static_dir, Path::new(out), &|path| {
+            !is_ignored(&options.site.server.ignore_files, path)
+        })?;
+        println!(
+            "📦 Copied {} static files from {}",
+            copied,
+            static_dir.display()
+        );
+    }
+
+    report.stage("assets");
+
+    let tera_arc = get_tera(&template_dirs(&options.site))?;
+    // Re-registered every build so the filter follows the current base URL and the
+    // functions the current posts
+    let base_url = options.base_url.clone();
+    tera_arc.write().unwrap().register_filter(
+        "absolute_url",
+        move |value: &tera::Value, _: &HashMap<String, tera::Value>| {
+            let path = tera::try_get_value!("absolute_url", "value", String, value);
+            Ok(tera::Value::String(absolute_url(&base_url, &path)))
+        },
+    );
+    let posts_data: Vec<_> = posts.iter().map(functions::post_data).collect();
+    // A post page can show any post through these functions, and goes through the
+    // `absolute_url` filter and minification outside of its context, so they all count as
+    // part of it when deciding whether the page needs re-rendering
+    let render_seed = {
+        let mut hasher = DefaultHasher::new();
+        serde_json::Value::Array(posts_data.clone())
+            .to_string()
+            .hash(&mut hasher);
+        (&options.base_url, options.minify).hash(&mut hasher);
+        hasher.finish()
+    };
+    functions::register(&mut tera_arc.write().unwrap(), posts_data);
+    let tera = tera_arc.read().unwrap();
+
+    let contents = posts
+        .iter_mut()
+        .chain(&mut drafts)
+        .map(|p| (&p.source, &mut p.html_content))
+        .chain(pages.iter_mut().map(|p| (&p.source, &mut p.html_content)));
+    for (source, html) in contents {
+        match shortcodes::expand(&tera, html) {
+            Ok(expanded) => *html = expanded,
+            Err(e) => report::error(format!("{}: {}", source.display(), e)),
+        }
+    }
+    let series = collect_series(&posts);
+
+    // Site-wide values available to every template. Custom `[extra]` values go in first so
+    // they can't shadow the generator's own variables
+    let mut base_context = Context::new();
+    for (key, value) in &options.site.extra {
+        base_context.insert(key.as_str(), value);
+    }
+    let nav_pages: Vec<_> = pages
+        .iter()
+        .map(|p| serde_json::json!({ "title": p.title, "slug": p.slug }))
+        .collect();
+    base_context.insert("nav_pages", &nav_pages);
+    base_context.insert("base_url", &options.base_url);
+    base_context.insert("archive_url", &canonical_path("/archive/"));
+    base_context.insert("search_url", &canonical_path("/search/"));
+    let mut site = options.site.clone();
+    if site.menu.is_empty() {
+        site.menu = pages
+            .iter()
+            .map(|p| MenuItem {
+                label: p.title.clone(),
+                url: page_path(p),
+                order: p.order,
+                external: false,
+            })
+            .collect();
+    }
+    base_context.insert("site", &site);
+    base_context.insert(
+        "data",
+        &data::load_data(Path::new(&options.site.server.data_dir)),
+    );
+    base_context.insert("image_sizes", images::SIZES);
+
+    // Languages with posts, default first, each with the URL of its index
+    let mut languages: Vec<&str> = posts.iter().map(|p| p.lang.as_str()).collect();
+    languages.push(DEFAULT_LANG);
+    languages.sort_by_key(|lang| (*lang != DEFAULT_LANG, *lang));
+    languages.dedup();
+    let languages_data: Vec<_> = languages
+        .iter()
+        .map(|lang| serde_json::json!({ "lang": lang, "url": index_path(lang) }))
+        .collect();
+    base_context.insert("languages", &languages_data);
+
+    for page in &pages {
+        let html = generate_page(&tera, &base_context, page);
+        let output_path = format!("{}/{}/index.html", out, page.slug);
+        write_html(options, &output_path, &html)?;
+        println!("📃 Generated: {}", output_path);
+    }
+
+    // Only re-render posts whose template context changed since the last build; this
+    // covers edits to the post itself as well as to its series, related posts or navigation
+    let mut render_cache = RENDER_CACHE.get_or_init(Default::default).lock().unwrap();
+    let previous_renders = &*render_cache;
+    let rendered = posts
+        .par_iter()
+        .enumerate()
+        .map(|(index, post)| {
+            let post_series = post
+                .series
+                .as_ref()
+                .and_then(|name| series.iter().find(|s| &s.name == name));
+            let related = related_posts(post, &posts);
+            let translations: Vec<&Post> = posts
+                .iter()
+                .filter(|p| p.slug == post.slug && p.lang != post.lang)
+                .collect();
+            // Posts are sorted newest first, so the previous post comes after this one
+            let previous = posts[index + 1..].iter().find(|p| p.lang == post.lang);
+            let next = posts[..index].iter().rev().find(|p| p.lang == post.lang);
+            let context = post_context(
+                &base_context,
+                post,
+                post_series,
+                (previous, next),
+                &related,
+                &translations,
+                &images,
+            );
+            let output_path = output_file(out, &post.path);
+
+            let context_hash = hash_context(&context, render_seed);
+            if previous_renders.get(&output_path) == Some(&context_hash)
+                && Path::new(&output_path).exists()
+            {
+                track_output(&output_path);
+                return Ok(None);
+            }
+
+            // A page that failed to render isn't cached, so it's tried again next build
+            let (html, context_hash) = match generate_post_page(&tera, &context, post) {
+                Ok(html) => (html, Some(context_hash)),
+                Err(message) => {
+                    report::error(message.clone());
+                    (generate_error_page(&message), None)
+                }
+            };
+            write_html(options, &output_path, &html)?;
+            println!("📄 Generated: {}", output_path);
+            Ok(Some((output_path, context_hash)))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+
+    for draft in &drafts {
+        let context = post_context(&base_context, draft, None, (None, None), &[], &[], &images);
+        let html = generate_post_page(&tera, &context, draft).unwrap_or_else(|message| {
+            report::error(message.clone());
+            generate_error_page(&message)
+        });
+        let output_path = output_file(out, &draft.path);
+        write_html(options, &output_path, &html)?;
+        println!("🚧 Draft preview: {}", output_path);
+    }
 
-    // Copy images folder if it exists
-    let images_src = "posts/images";
-    let images_dest = "output/images";
-    if Path::new(images_src).exists() {
-        fs::create_dir_all(images_dest)?;
-        for entry in fs::read_dir(images_src)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_file() {
-                let file_name = path.file_name().unwrap();
-                let dest_path = format!("output/images/{}", file_name.to_string_lossy());
-                fs::copy(&path, &dest_path)?;
-                println!("🖼️  Copied: {}", dest_path);
+    // Page bundle assets go next to the post so relative links keep working
+    for post in posts.iter().chain(&drafts) {
+        if let Some(bundle) = &post.bundle {
+            let dest = format!("{}{}", out, post_dir(&post.path));
+            let copied = copy_dir(bundle, Path::new(&dest), &|path| {
+                path.extension().and_then(|s| s.to_str()) != Some("md")
+                    && !is_ignored(&options.site.server.ignore_files, path)
+            })?;
+            if copied > 0 {
+                println!("📦 Copied {} bundle files to {}", copied, dest);
             }
         }
     }
 
-    let tera_arc = get_tera();
+    let unchanged = rendered.iter().filter(|r| r.is_none()).count();
+    for (output_path, context_hash) in rendered.into_iter().flatten() {
+        match context_hash {
+            Some(hash) => render_cache.insert(output_path, hash),
+            None => render_cache.remove(&output_path),
+        };
+    }
+    drop(render_cache);
+    if unchanged > 0 {
+        println!("⏭️  Skipped {} unchanged posts", unchanged);
+    }
+
+    report.stage("posts");
+
+    // Posts by year and month, also given to index pages as post counts for archive widgets
+    let mut archive: BTreeMap<i32, BTreeMap<u32, Vec<&Post>>> = BTreeMap::new();
+    for post in &posts {
+        if let Some(date) = parse_date(&post.date) {
+            archive
+                .entry(date.year())
+                .or_default()
+                .entry(date.month())
+                .or_default()
+                .push(post);
+        }
+    }
+    let archive_counts = archive_counts(&archive);
+    let mut index_context = base_context.clone();
+    index_context.insert("archive", &archive_counts);
+
+    // Generate index pages, one paginated list per language. Featured posts get their own
+    // section on the first page and are left out of the paginated chronological list
+    for lang in &languages {
+        let prefix = lang_prefix(lang);
+        let (featured, regular): (Vec<&Post>, Vec<&Post>) = posts
+            .iter()
+            .filter(|p| p.lang == *lang)
+            .partition(|p| p.featured);
+        let index_pages: Vec<&[&Post]> = if regular.is_empty() {
+            vec![&[]]
+        } else {
+            regular.chunks(options.posts_per_page.max(1)).collect()
+        };
+        let page_url = |page: usize| match page {
+            1 => index_path(lang),
+            n => canonical_path(&format!("{}/page/{}/", prefix, n)),
+        };
+
+        for (index, page_posts) in index_pages.iter().enumerate() {
+            let page = index + 1;
+            let pagination = serde_json::json!({
+                "current": page,
+                "total": index_pages.len(),
+                "prev_url": (page > 1).then(|| page_url(page - 1)),
+                "next_url": (page < index_pages.len()).then(|| page_url(page + 1)),
+            });
+            let page_featured = if page == 1 { featured.as_slice() } else { &[] };
+
+            let index_html = generate_index_page(
+                &tera,
+                &index_context,
+                lang,
+                page_featured,
+                page_posts,
+                &pagination,
+                &images,
+            );
+
+            let output_path = output_file(out, &page_url(page));
+            write_html(options, &output_path, &index_html)?;
+            println!("🏠 Generated: {}", output_path);
+        }
+    }
 
+    // Each section lists its posts at its own path, e.g. `/notes/`
+    let mut sections: BTreeMap<&str, Vec<&Post>> = BTreeMap::new();
     for post in &posts {
-        let tera = tera_arc.lock().unwrap();
-        let html = generate_post_page(&tera, post);
-        drop(tera);
-        let output_path = format!("output/{}.html", post.slug);
-        fs::write(&output_path, html)?;
-        println!("📄 Generated: {}", output_path);
+        if let Some(section) = &post.section {
+            sections.entry(section).or_default().push(post);
+        }
+    }
+    for (section, section_posts) in &sections {
+        let path = canonical_path(&format!("/{}/", section));
+        if posts.iter().any(|p| p.path == path) {
+            report::warn(format!(
+                "No list page for section \"{}\", a post already uses {}",
+                section, path
+            ));
+            continue;
+        }
+        let html = generate_section_page(&tera, &base_context, section, section_posts);
+        let output_path = output_file(out, &path);
+        write_html(options, &output_path, &html)?;
+        println!("📂 Generated: {}", output_path);
+    }
+
+    // Generate series index pages
+    if !series.is_empty() {
+        fs::create_dir_all(format!("{}/series", out))?;
+    }
+    for s in &series {
+        let html = generate_series_page(&tera, &base_context, s);
+        let output_path = format!("{}/series/{}/index.html", out, s.slug);
+        write_html(options, &output_path, &html)?;
+        println!("📚 Generated: {}", output_path);
+    }
+
+    // Generate archive pages: overall, per year and per month
+
+    let mut archive_pages = vec![(
+        format!("{}/archive/index.html", out),
+        "Archive".to_string(),
+        archive
+            .iter()
+            .rev()
+            .map(|(year, months)| {
+                let posts = months.values().rev().flatten().copied().collect();
+                let url = canonical_path(&format!("/archive/{}/", year));
+                archive_group(year.to_string(), url, posts)
+            })
+            .collect::<Vec<_>>(),
+    )];
+    for (year, months) in &archive {
+        let month_groups = months
+            .iter()
+            .rev()
+            .map(|(month, posts)| {
+                archive_group(
+                    month_name(*month),
+                    canonical_path(&format!("/archive/{}/{:02}/", year, month)),
+                    posts.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        archive_pages.push((
+            format!("{}/archive/{}/index.html", out, year),
+            year.to_string(),
+            month_groups,
+        ));
+        for (month, posts) in months {
+            archive_pages.push((
+                format!("{}/archive/{}/{:02}/index.html", out, year, month),
+                format!("{} {}", month_name(*month), year),
+                vec![archive_group(
+                    month_name(*month),
+                    String::new(),
+                    posts.clone(),
+                )],
+            ));
+        }
     }
 
-    // Generate index page
-    let tera = tera_arc.lock().unwrap();
-    let index_html = generate_index_page(&tera, &posts);
-    drop(tera);
-    fs::write("output/index.html", index_html)?;
-    println!("🏠 Generated: output/index.html");
+    for (output_path, heading, groups) in &archive_pages {
+        let html = generate_archive_page(&tera, &base_context, heading, groups);
+        if let Some(parent) = Path::new(output_path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_html(options, output_path, &html)?;
+        println!("🗓️  Generated: {}", output_path);
+    }
 
+    let not_found_html = generate_not_found_page(&tera, &base_context);
+    write_html(options, format!("{}/404.html", out), &not_found_html)?;
+    println!("🚫 Generated: {}/404.html", out);
+
+    // Search index and the page that queries it in the browser
+    write_output(
+        format!("{}/search-index.json", out),
+        generate_search_index(&posts),
+    )?;
+    println!("🔎 Generated: {}/search-index.json", out);
+    write_output(
+        format!("{}/archive.json", out),
+        serde_json::to_string(&archive_counts).unwrap_or_else(|_| "[]".to_string()),
+    )?;
+    println!("🗓️  Generated: {}/archive.json", out);
+    let search_html = generate_search_page(&tera, &base_context);
+    write_html(options, format!("{}/search/index.html", out), &search_html)?;
+    println!("🔎 Generated: {}/search/index.html", out);
+
+    report.stage("listings");
+
+    write_output(
+        format!("{}/sitemap.xml", out),
+        generate_sitemap(&options.base_url, &languages, &posts, &pages),
+    )?;
+    println!("🗺️  Generated: {}/sitemap.xml", out);
+
+    write_output(
+        format!("{}/robots.txt", out),
+        generate_robots_txt(&options.base_url, options.site.robots_disallow),
+    )?;
+    println!("🤖 Generated: {}/robots.txt", out);
+
+    write_output(
+        format!("{}/humans.txt", out),
+        generate_humans_txt(options, &languages, &posts),
+    )?;
+    println!("🧑 Generated: {}/humans.txt", out);
+    if let Some(contact) = &options.site.security_contact {
+        write_output(
+            format!("{}/.well-known/security.txt", out),
+            generate_security_txt(&options.base_url, contact),
+        )?;
+        println!("🔐 Generated: {}/.well-known/security.txt", out);
+    }
+
+    // Each language has its own feeds next to its index
+    for lang in &languages {
+        let prefix = lang_prefix(lang);
+        let lang_posts: Vec<&Post> = posts.iter().filter(|p| p.lang == *lang).collect();
+        let feed_items = feed::feed_items(options, &lang_posts);
+        let feed_url = format!("{}{}", options.base_url, prefix);
+        for (name, xml) in [
+            (
+                "rss.xml",
+                feed::generate_rss(&options.site, &feed_url, &feed_items),
+            ),
+            (
+                "atom.xml",
+                feed::generate_atom(&options.site, &feed_url, &feed_items),
+            ),
+            (
+                "feed.json",
+                feed::generate_json_feed(&options.site, &feed_url, &feed_items),
+            ),
+        ] {
+            let output_path = format!("{}{}/{}", out, prefix, name);
+            write_output(&output_path, xml)?;
+            println!("📡 Generated: {}", output_path);
+        }
+    }
+
+    // A feed per tag so readers can follow a single topic
+    // Keyed by feed path so tags differing only in case or punctuation share a feed
+    let mut tags: BTreeMap<String, (&str, Vec<&Post>)> = BTreeMap::new();
+    for post in &posts {
+        for tag in &post.tags {
+            tags.entry(tag_feed_path(tag))
+                .or_insert_with(|| (tag, Vec::new()))
+                .1
+                .push(post);
+        }
+    }
+    for (feed_path, (tag, tag_posts)) in &tags {
+        let feed_items = feed::feed_items(options, tag_posts);
+        let xml = feed::generate_tag_rss(
+            &options.site,
+            &options.base_url,
+            tag,
+            feed_path,
+            &feed_items,
+        );
+        write_output(format!("{}{}", out, feed_path), xml)?;
+    }
+    if !tags.is_empty() {
+        println!("🏷️  Generated {} tag feeds", tags.len());
+    }
+
+    // Redirect stubs for renamed posts and the config's `[[redirects]]`, plus a `_redirects`
+    // table for the server and static hosts
+    let mut redirects = Vec::new();
+    for post in &posts {
+        for alias in &post.aliases {
+            if posts
+                .iter()
+                .any(|p| alias.trim_end_matches('/') == p.path.trim_end_matches('/'))
+            {
+                report::warn(format!(
+                    "{}: alias {} points at an existing post, skipping",
+                    post.source.display(),
+                    alias
+                ));
+                continue;
+            }
+            redirects.push((alias.clone(), post.path.clone(), 301));
+        }
+    }
+    for redirect in &options.site.redirects {
+        if let Some(from) = normalize_alias(&redirect.from) {
+            redirects.push((from, redirect.to.clone(), redirect.status));
+        }
+    }
+    let mut table = String::new();
+    for (from, to, status) in &redirects {
+        let output_path = format!("{}{}/index.html", out, from);
+        if is_output(Path::new(&output_path)) {
+            report::warn(format!(
+                "Redirect from {} would replace a generated page, skipping",
+                from
+            ));
+            continue;
+        }
+        if let Some(parent) = Path::new(&output_path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_html(options, &output_path, &generate_redirect_page(to))?;
+        table.push_str(&format!("{} {} {}\n", from, to, status));
+        println!("↪️  Generated: {}", output_path);
+    }
+    write_output(format!("{}/_redirects", out), table)?;
+    report.stage("feeds");
+
+    if options.precompress {
+        let outputs: Vec<PathBuf> = OUTPUTS
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect();
+        let compressed = compress::compress_outputs(&outputs)?;
+        println!("🗜️  Compressed {} files", compressed);
+        report.stage("compress");
+    }
+
+    // Written last so it can include the final timings
+    let build_report = Path::new(out).join(BUILD_REPORT);
+    if options.build_report {
+        track_output(&build_report);
+    }
+    remove_stale_outputs(Path::new(out))?;
+
+    let mut outputs: Vec<PathBuf> = OUTPUTS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap()
+        .iter()
+        .cloned()
+        .collect();
+    outputs.sort();
+
+    // Catch typos in cross-post links and missing images
+    let html_files: Vec<PathBuf> = outputs
+        .iter()
+        .filter(|path| path.extension().is_some_and(|ext| ext == "html"))
+        .cloned()
+        .collect();
+    let broken_links = links::check_links(Path::new(out), &html_files);
+    for link in &broken_links {
+        report::warn(format!("Broken link in {}", link));
+    }
+    report.stage("checks");
+
+    let report_data = report.finish(&outputs);
+    if options.build_report {
+        fs::write(
+            &build_report,
+            serde_json::to_string_pretty(&report_data).unwrap_or_default(),
+        )?;
+    }
+
+    let errors = report::errors();
+    if options.strict && !errors.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} problems:\n{}", errors.len(), errors.join("\n")),
+        ));
+    }
+    if options.strict_links && !broken_links.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} broken internal links", broken_links.len()),
+        ));
+    }
+
+    Ok(BuildSummary {
+        next_scheduled,
+        posts: report.posts,
+    })
+}
+
+/// Writes a generated HTML page, minified when building for production
+fn write_html(options: &BuildOptions, path: impl AsRef<Path>, html: &str) -> io::Result<()> {
+    if options.minify {
+        write_output(path, minify::minify_html(html))
+    } else {
+        write_output(path, html)
+    }
+}
+
+/// Writes a file into `output/`, creating its directory, and records it as part of the
+/// current build
+fn write_output(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    if let Some(parent) = path.as_ref().parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, contents)?;
+    track_output(path);
     Ok(())
 }
 
-fn parse_post(path: &Path, content: &str) -> Option<Post> {
+/// Whether the current build has already written `path`
+fn is_output(path: &Path) -> bool {
+    OUTPUTS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap()
+        .contains(path)
+}
+
+/// Records a file as produced by the current build, including files left untouched
+/// because they were already up to date, so stale output cleanup keeps it
+pub(crate) fn track_output(path: impl AsRef<Path>) {
+    OUTPUTS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap()
+        .insert(path.as_ref().to_path_buf());
+}
+
+/// Deletes files the previous build generated that this one didn't (e.g. pages of deleted
+/// or renamed posts), then saves this build's file list for the next one. Only files listed
+/// in the manifest are ever removed, so anything else placed in `output/` is left alone
+fn remove_stale_outputs(output_dir: &Path) -> io::Result<()> {
+    let outputs = OUTPUTS.get_or_init(Default::default).lock().unwrap();
+    let manifest_path = output_dir.join(OUTPUT_MANIFEST);
+    let previous = fs::read_to_string(&manifest_path).unwrap_or_default();
+
+    for stale in previous
+        .lines()
+        .map(PathBuf::from)
+        .filter(|path| !outputs.contains(path))
+    {
+        if fs::remove_file(&stale).is_ok() {
+            println!("🧹 Removed: {}", stale.display());
+            // Drop directories the removal left empty, e.g. `output/page/3/`
+            for dir in stale.ancestors().skip(1) {
+                if dir == output_dir || fs::remove_dir(dir).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    let mut manifest: Vec<_> = outputs
+        .iter()
+        .map(|path| path.to_string_lossy().to_string())
+        .collect();
+    manifest.sort();
+    fs::write(manifest_path, manifest.join("\n"))
+}
+
+/// Recursively copies the contents of `src` into `dest`, skipping files `include` rejects,
+/// and returns the number of files copied
+fn copy_dir(src: &Path, dest: &Path, include: &dyn Fn(&Path) -> bool) -> io::Result<usize> {
+    fs::create_dir_all(dest)?;
+    let mut copied = 0;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let target = dest.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copied += copy_dir(&entry.path(), &target, include)?;
+        } else if include(&entry.path()) {
+            fs::copy(entry.path(), &target)?;
+            track_output(&target);
+            copied += 1;
+        }
+    }
+    Ok(copied)
+}
+
+/// Expands the permalink pattern for a post
+fn permalink(pattern: &str, post: &Post) -> String {
+    let date = parse_date(&post.date);
+    let field = |format: &str| {
+        date.map(|d| d.format(format).to_string())
+            .unwrap_or_default()
+    };
+    let mut path = pattern
+        .replace(":year", &field("%Y"))
+        .replace(":month", &field("%m"))
+        .replace(":day", &field("%d"))
+        .replace(":section", post.section.as_deref().unwrap_or_default())
+        .replace(":slug", &post.slug);
+    // Posts outside a section leave an empty `:section` segment behind
+    while path.contains("//") {
+        path = path.replace("//", "/");
+    }
+    canonical_path(&format!("{}{}", lang_prefix(&post.lang), site_path(&path)))
+}
+
+/// Markdown files under `dir` and its subdirectories, skipping hidden entries and the
+/// `ignore_files` ones. Inside a page bundle only its `index.md` is a post
+pub(crate) fn markdown_files(dir: &Path, ignore_files: &[String]) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let hidden = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if hidden || is_ignored(ignore_files, &path) {
+            continue;
+        }
+        if path.is_dir() {
+            let index = path.join("index.md");
+            if index.is_file() {
+                files.push(index);
+            } else {
+                files.extend(markdown_files(&path, ignore_files)?);
+            }
+        } else if path.extension().and_then(|s| s.to_str()) == Some("md") {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Directory part of a site path, e.g. `/2024/hello/` for `/2024/hello/` and `/2024/` for
+/// `/2024/hello.html`
+fn post_dir(path: &str) -> &str {
+    &path[..path.rfind('/').map_or(0, |i| i + 1)]
+}
+
+/// File in the output directory that serves a site path, `index.html` for directory-style
+/// paths, with or without their trailing slash
+fn output_file(output_dir: &str, path: &str) -> String {
+    if path.ends_with('/') {
+        format!("{}{}index.html", output_dir, path)
+    } else if !is_file_path(path) {
+        format!("{}{}/index.html", output_dir, path)
+    } else {
+        format!("{}{}", output_dir, path)
+    }
+}
+
+/// Whether a site path names a file, by the extension of its last segment
+fn is_file_path(path: &str) -> bool {
+    path.rsplit('/')
+        .next()
+        .is_some_and(|name| name.contains('.'))
+}
+
+/// A directory-style site path as the `trailing_slash` setting writes it, `/hello/` or
+/// `/hello`. The root and paths to files stay as they are
+pub(crate) fn canonical_path(path: &str) -> String {
+    let trimmed = path.trim_end_matches('/');
+    if trimmed.is_empty() || path.contains("://") || is_file_path(trimmed) {
+        path.to_string()
+    } else if TRAILING_SLASH.load(Ordering::Relaxed) {
+        format!("{}/", trimmed)
+    } else {
+        trimmed.to_string()
+    }
+}
+
+/// Site path of a standalone page, e.g. `/about/`
+fn page_path(page: &Page) -> String {
+    canonical_path(&format!("/{}/", page.slug))
+}
+
+/// Site path of the post index for a language, `/` for the default one
+fn index_path(lang: &str) -> String {
+    canonical_path(&format!("{}/", lang_prefix(lang)))
+}
+
+/// Directory a language's feeds are written to, always ending in a slash so feed file
+/// names can follow it
+fn feed_base(lang: &str) -> String {
+    format!("{}/", lang_prefix(lang))
+}
+
+/// Parses a frontmatter date, either a plain `YYYY-MM-DD` (midnight) or with a time of day
+pub(crate) fn parse_date(date: &str) -> Option<NaiveDateTime> {
+    let date = date.trim();
+    NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M"))
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+        })
+}
+
+/// Splits a markdown file into its frontmatter lines (with line numbers for error
+/// reporting) and body, or `None` if it doesn't start with a `---` frontmatter block
+pub(crate) fn split_frontmatter(content: &str) -> Option<(Vec<(usize, &str)>, String)> {
     let mut lines = content.lines();
 
     // Expect frontmatter: ---
@@ -109,57 +1234,559 @@ fn parse_post(path: &Path, content: &str) -> Option<Post> {
         return None;
     }
 
-    let mut frontmatter = String::new();
-    let mut html_content = String::new();
+    let mut frontmatter = Vec::new();
+    let mut body = String::new();
     let mut in_frontmatter = true;
 
-    for line in lines {
+    for (index, line) in lines.enumerate() {
         if in_frontmatter {
             if line == "---" {
                 in_frontmatter = false;
                 continue;
             }
-            frontmatter.push_str(line);
-            frontmatter.push('\n');
+            frontmatter.push((index + 2, line));
         } else {
-            html_content.push_str(line);
-            html_content.push('\n');
+            body.push_str(line);
+            body.push('\n');
         }
     }
 
+    Some((frontmatter, body))
+}
+
+/// A frontmatter line's key and raw value, or the line if it isn't `key: value`
+pub(crate) type FrontmatterField<'a> = Result<(&'a str, String), &'a str>;
+
+/// The `key: value` fields of a frontmatter block with their line numbers, or the lines
+/// that aren't one. Block lists, `- item` lines under an empty `key:` as YAML writers put
+/// them, become `[item, ...]` values
+pub(crate) fn frontmatter_fields<'a>(
+    lines: &[(usize, &'a str)],
+) -> Vec<(usize, FrontmatterField<'a>)> {
+    let mut fields: Vec<(usize, FrontmatterField)> = Vec::new();
+    let mut in_list = false;
+    for &(line_number, line) in lines {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let item = line
+            .trim()
+            .strip_prefix('-')
+            .filter(|item| item.is_empty() || item.starts_with(' '));
+        if let (Some(item), true, Some((_, Ok((_, value))))) = (item, in_list, fields.last_mut()) {
+            *value = match value.strip_suffix(']') {
+                Some(items) => format!("{}, {}]", items, item.trim()),
+                None => format!("[{}]", item.trim()),
+            };
+            continue;
+        }
+        let field = line
+            .split_once(':')
+            .map(|(key, value)| (key.trim(), value.to_string()))
+            .ok_or(line);
+        in_list = field
+            .as_ref()
+            .is_ok_and(|(_, value)| value.trim().is_empty());
+        fields.push((line_number, field));
+    }
+    fields
+}
+
+/// A frontmatter value without its YAML quotes, e.g. `Hello` for `"Hello"` and `It's` for
+/// `'It''s'`
+pub(crate) fn unquote(value: &str) -> String {
+    let value = value.trim();
+    let quoted = value.len() >= 2
+        && ((value.starts_with('"') && value.ends_with('"'))
+            || (value.starts_with('\'') && value.ends_with('\'')));
+    quoted
+        .then(|| serde_yaml::from_str::<String>(value).ok())
+        .flatten()
+        .unwrap_or_else(|| value.trim_matches('"').to_string())
+}
+
+fn parse_post(path: &Path, content: &str) -> io::Result<Option<Post>> {
+    let Some((frontmatter, html_content)) = split_frontmatter(content) else {
+        return Ok(None);
+    };
+
     // Parse frontmatter as YAML-like key: value
     let mut title = String::new();
     let mut date = String::new();
+    let mut updated = None;
     let mut excerpt = String::new();
+    let mut description = None;
+    let mut keywords = Vec::new();
+    let mut canonical_url = None;
+    let mut lang = DEFAULT_LANG.to_string();
+    let mut cover = None;
+    let mut featured = false;
+    let mut draft = false;
+    let mut tags = Vec::new();
+    let mut aliases = Vec::new();
+    let mut slug = None;
+    let mut series = None;
+    let mut series_part = None;
+
+    let mut extra = BTreeMap::new();
+    let mut problems = Vec::new();
+    let mut seen = HashSet::new();
+
+    for (line_number, field) in frontmatter_fields(&frontmatter) {
+        let location = format!("{}:{}", path.display(), line_number);
+        let (key, raw_value) = match field {
+            Ok(field) => field,
+            Err(line) => {
+                problems.push(format!(
+                    "{}: expected `key: value`, found \"{}\"",
+                    location, line
+                ));
+                continue;
+            }
+        };
+        let raw_value = raw_value.as_str();
+        let value = unquote(raw_value);
+        let value = value.as_str();
+        seen.insert(key);
+
+        match key {
+            "title" => title = value.to_string(),
+            "date" | "updated" if parse_date(value).is_none() => problems.push(format!(
+                "{}: `{}` must be a date like 2024-05-01 or 2024-05-01T09:30:00, found \"{}\"",
+                location,
+                key.trim(),
+                value
+            )),
+            "date" => date = value.to_string(),
+            "updated" => updated = Some(value.to_string()),
+            "excerpt" => excerpt = value.to_string(),
+            "description" => description = Some(value.to_string()),
+            "keywords" => keywords = parse_list(raw_value),
+            "canonical_url" if !value.starts_with("http://") && !value.starts_with("https://") => {
+                problems.push(format!(
+                    "{}: `canonical_url` must be an absolute http(s) URL, found \"{}\"",
+                    location, value
+                ))
+            }
+            "canonical_url" => canonical_url = Some(value.to_string()),
+            "lang" if !is_valid_lang(value) => problems.push(format!(
+                "{}: `lang` must be a language tag like en or pt-BR, found \"{}\"",
+                location, value
+            )),
+            "lang" => lang = value.to_string(),
+            "cover" => cover = Some(site_path(value)),
+            "featured" => match value.parse() {
+                Ok(flag) => featured = flag,
+                Err(_) => problems.push(format!(
+                    "{}: `featured` must be true or false, found \"{}\"",
+                    location, value
+                )),
+            },
+            "draft" => match value.parse() {
+                Ok(flag) => draft = flag,
+                Err(_) => problems.push(format!(
+                    "{}: `draft` must be true or false, found \"{}\"",
+                    location, value
+                )),
+            },
+            "slug" if !is_valid_slug(value) => problems.push(format!(
+                "{}: invalid slug \"{}\" (use lowercase letters, digits, '-' and '_')",
+                location, value
+            )),
+            "slug" => slug = Some(value.to_string()),
+            "tags" => tags = parse_list(raw_value),
+            "aliases" => {
+                for alias in parse_list(raw_value) {
+                    match normalize_alias(&alias) {
+                        Some(alias) => aliases.push(alias),
+                        None => problems.push(format!(
+                            "{}: invalid alias \"{}\" (expected a site path like /old-url)",
+                            location, alias
+                        )),
+                    }
+                }
+            }
+            "series" => series = Some(value.to_string()),
+            "series_part" => match value.parse() {
+                Ok(part) if part > 0 => series_part = Some(part),
+                _ => problems.push(format!(
+                    "{}: `series_part` must be a positive number, found \"{}\"",
+                    location, value
+                )),
+            },
+            // Anything else is passed through to templates as `extra.<key>`
+            key => {
+                if let Some(known) = suggest_key(key) {
+                    report::warn(format!(
+                        "{}: unknown key `{}`, did you mean `{}`?",
+                        location, key, known
+                    ));
+                }
+                extra.insert(key.to_string(), value.to_string());
+            }
+        }
+    }
 
-    for line in frontmatter.lines() {
-        if let Some(value) = line.strip_prefix("title: ") {
-            title = value.trim_matches('"').to_string();
-        } else if let Some(value) = line.strip_prefix("date: ") {
-            date = value.trim_matches('"').to_string();
-        } else if let Some(value) = line.strip_prefix("excerpt: ") {
-            excerpt = value.trim_matches('"').to_string();
+    for required in ["title", "date"] {
+        if !seen.contains(required) {
+            problems.push(format!(
+                "{}: missing required field `{}`",
+                path.display(),
+                required
+            ));
         }
     }
+    if !problems.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            problems.join("\n"),
+        ));
+    }
 
-    let slug = path
+    // A language suffix in the file name (`hello.id.md`) marks a translation of `hello.md`
+    let stem = path
         .file_stem()
         .and_then(|s| s.to_str())
-        .unwrap_or("untitled")
-        .to_string();
+        .unwrap_or("untitled");
+    let stem = match stem.rsplit_once('.') {
+        Some((name, suffix)) if is_lang_suffix(suffix) => {
+            if !seen.contains("lang") {
+                lang = suffix.to_string();
+            }
+            name
+        }
+        _ => stem,
+    };
+
+    // Page bundles (`my-post/index.md`) are named after their directory
+    let slug = slug.unwrap_or_else(|| {
+        if stem == "index" {
+            path.parent()
+                .and_then(|dir| dir.file_name())
+                .and_then(|s| s.to_str())
+                .unwrap_or("untitled")
+                .to_string()
+        } else {
+            stem.to_string()
+        }
+    });
+
+    // Fall back to the file's modification time when no `updated` date is given
+    let updated = updated
+        .or_else(|| {
+            let modified = fs::metadata(path).and_then(|m| m.modified()).ok()?;
+            Some(
+                DateTime::<Local>::from(modified)
+                    .format("%Y-%m-%d")
+                    .to_string(),
+            )
+        })
+        .unwrap_or_else(|| date.clone());
+
+    let word_count = html_content.split_whitespace().count();
+    let reading_time = word_count.div_ceil(WORDS_PER_MINUTE).max(1);
 
     let html = markdown_to_html(&html_content);
 
-    Some(Post {
+    Ok(Some(Post {
+        source: path.to_path_buf(),
         title,
+        path: format!("/{}/", slug),
+        section: None,
+        bundle: None,
         slug,
         date,
+        updated,
         excerpt,
+        description,
+        keywords,
+        canonical_url,
+        lang,
+        cover,
+        featured,
+        draft,
+        tags,
+        aliases,
+        series,
+        series_part,
+        extra,
+        word_count,
+        reading_time,
         html_content: html,
-    })
+    }))
+}
+
+fn parse_page(path: &Path, content: &str) -> io::Result<Option<Page>> {
+    let Some((frontmatter, body)) = split_frontmatter(content) else {
+        return Ok(None);
+    };
+
+    let mut title = None;
+    let mut slug = None;
+    let mut order = 0;
+    let mut extra = BTreeMap::new();
+    let mut problems = Vec::new();
+
+    for (line_number, field) in frontmatter_fields(&frontmatter) {
+        let location = format!("{}:{}", path.display(), line_number);
+        let (key, raw_value) = match field {
+            Ok(field) => field,
+            Err(line) => {
+                problems.push(format!(
+                    "{}: expected `key: value`, found \"{}\"",
+                    location, line
+                ));
+                continue;
+            }
+        };
+        let value = unquote(&raw_value);
+        let value = value.as_str();
+
+        match key {
+            "title" => title = Some(value.to_string()),
+            "slug" if !is_valid_slug(value) => problems.push(format!(
+                "{}: invalid slug \"{}\" (use lowercase letters, digits, '-' and '_')",
+                location, value
+            )),
+            "slug" => slug = Some(value.to_string()),
+            "order" => match value.parse() {
+                Ok(value) => order = value,
+                Err(_) => problems.push(format!(
+                    "{}: `order` must be a number, found \"{}\"",
+                    location, value
+                )),
+            },
+            key => {
+                extra.insert(key.to_string(), value.to_string());
+            }
+        }
+    }
+
+    if title.is_none() {
+        problems.push(format!(
+            "{}: missing required field `title`",
+            path.display()
+        ));
+    }
+    if !problems.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            problems.join("\n"),
+        ));
+    }
+
+    Ok(Some(Page {
+        source: path.to_path_buf(),
+        title: title.unwrap_or_default(),
+        slug: slug.unwrap_or_else(|| {
+            path.file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or("untitled")
+                .to_string()
+        }),
+        order,
+        extra,
+        html_content: markdown_to_html(&body),
+    }))
+}
+
+/// Frontmatter keys understood by the generator
+const FRONTMATTER_KEYS: &[&str] = &[
+    "title",
+    "date",
+    "updated",
+    "excerpt",
+    "description",
+    "keywords",
+    "canonical_url",
+    "lang",
+    "cover",
+    "featured",
+    "draft",
+    "slug",
+    "tags",
+    "aliases",
+    "series",
+    "series_part",
+];
+
+/// Suggests the known frontmatter key an unknown key was probably meant to be (e.g. `titel`)
+fn suggest_key(key: &str) -> Option<&'static str> {
+    FRONTMATTER_KEYS
+        .iter()
+        .map(|known| (edit_distance(key, known), *known))
+        .filter(|(distance, _)| *distance > 0 && *distance <= 2)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, known)| known)
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+/// Parses an inline frontmatter list such as `[rust, "web dev"]` (brackets optional)
+pub(crate) fn parse_list(value: &str) -> Vec<String> {
+    value
+        .trim()
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .split(',')
+        .map(unquote)
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Normalizes an alias to `/path/without/extension`, rejecting URLs and parent-directory segments
+fn normalize_alias(alias: &str) -> Option<String> {
+    let path = alias.trim().trim_matches('/').trim_end_matches(".html");
+    if path.is_empty() || path.contains("://") || path.split('/').any(|part| part == "..") {
+        return None;
+    }
+    Some(format!("/{}", path))
+}
+
+/// Lowercases text and joins its alphanumeric runs with dashes, e.g. "Rust 101!" -> "rust-101"
+pub(crate) fn slugify(text: &str) -> String {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Site path of a tag's RSS feed, e.g. `/tags/web-dev/feed.xml`
+pub(crate) fn tag_feed_path(tag: &str) -> String {
+    format!("/tags/{}/feed.xml", slugify(tag))
+}
+
+/// Groups posts by series name, ordering each series by `series_part` and then by date
+fn collect_series(posts: &[Post]) -> Vec<Series<'_>> {
+    let mut by_name: BTreeMap<&str, Vec<&Post>> = BTreeMap::new();
+    for post in posts {
+        if let Some(name) = &post.series {
+            by_name.entry(name).or_default().push(post);
+        }
+    }
+
+    by_name
+        .into_iter()
+        .map(|(name, mut parts)| {
+            parts.sort_by(|a, b| {
+                (a.series_part.unwrap_or(u32::MAX), &a.date)
+                    .cmp(&(b.series_part.unwrap_or(u32::MAX), &b.date))
+            });
+            Series {
+                name: name.to_string(),
+                slug: slugify(name),
+                parts,
+            }
+        })
+        .collect()
+}
+
+/// Picks the posts most similar to `post`, scoring shared tags above shared title words
+fn related_posts<'a>(post: &Post, posts: &'a [Post]) -> Vec<&'a Post> {
+    let title_words = |p: &Post| -> HashSet<String> {
+        p.title
+            .to_lowercase()
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| word.len() > 3)
+            .map(str::to_string)
+            .collect()
+    };
+    let words = title_words(post);
+
+    let mut scored: Vec<(usize, &Post)> = posts
+        .iter()
+        .filter(|other| other.slug != post.slug && other.lang == post.lang)
+        .map(|other| {
+            let shared_tags = other.tags.iter().filter(|t| post.tags.contains(t)).count();
+            let shared_words = title_words(other).intersection(&words).count();
+            (shared_tags * 3 + shared_words, other)
+        })
+        .filter(|(score, _)| *score > 0)
+        .collect();
+
+    // Stable sort keeps newer posts first among equal scores
+    scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    scored
+        .into_iter()
+        .take(RELATED_POSTS)
+        .map(|(_, p)| p)
+        .collect()
+}
+
+/// Loose BCP 47 check: a 2-3 letter primary language optionally followed by `-subtag`s
+fn is_valid_lang(lang: &str) -> bool {
+    let mut parts = lang.split('-');
+    let primary = parts.next().unwrap_or_default();
+    (2..=3).contains(&primary.len())
+        && primary.chars().all(|c| c.is_ascii_alphabetic())
+        && parts.all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+/// Language suffixes in file names are kept to lowercase two-letter codes, optionally with
+/// a region (`id`, `pt-BR`), so ordinary dotted names aren't mistaken for translations
+fn is_lang_suffix(suffix: &str) -> bool {
+    let (primary, region) = suffix.split_once('-').unwrap_or((suffix, "AA"));
+    primary.len() == 2
+        && primary.chars().all(|c| c.is_ascii_lowercase())
+        && region.len() == 2
+        && region.chars().all(|c| c.is_ascii_uppercase())
 }
 
-fn markdown_to_html(markdown: &str) -> String {
+/// URL prefix of a language's site tree: the default language lives at the root, others
+/// under `/<lang>`
+fn lang_prefix(lang: &str) -> String {
+    if lang == DEFAULT_LANG {
+        String::new()
+    } else {
+        format!("/{}", lang)
+    }
+}
+
+pub(crate) fn is_valid_slug(slug: &str) -> bool {
+    !slug.is_empty()
+        && !slug.starts_with('-')
+        && !slug.ends_with('-')
+        && slug
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+/// Turns a path relative to the site root (e.g. `images/foo.png`) into a root-relative URL,
+/// leaving absolute URLs untouched
+fn site_path(path: &str) -> String {
+    if path.starts_with('/') || path.contains("://") {
+        path.to_string()
+    } else {
+        format!("/{}", path)
+    }
+}
+
+/// Resolves a site path (or leaves an absolute URL) against the site's base URL
+pub(crate) fn absolute_url(base_url: &str, path: &str) -> String {
+    if path.contains("://") {
+        path.to_string()
+    } else {
+        format!("{}/{}", base_url, path.trim_start_matches('/'))
+    }
+}
+
+pub(crate) fn markdown_to_html(markdown: &str) -> String {
     let mut html = String::new();
     let mut in_code_block = false;
     let mut code_content = String::new();
@@ -219,7 +1846,7 @@ fn markdown_to_html(markdown: &str) -> String {
     html
 }
 
-fn process_inline_markdown(text: &str) -> String {
+pub(crate) fn process_inline_markdown(text: &str) -> String {
     let mut result = text.to_string();
 
     // Process in order: images, links, bold, italic
@@ -309,7 +1936,7 @@ fn parse_link(text: &str) -> String {
             let mut link_text = String::new();
             let mut found_close_bracket = false;
 
-            while let Some(inner_ch) = chars.next() {
+            for inner_ch in chars.by_ref() {
                 if inner_ch == ']' {
                     found_close_bracket = true;
                     break;
@@ -322,7 +1949,7 @@ fn parse_link(text: &str) -> String {
                 let mut url = String::new();
                 let mut found_close_paren = false;
 
-                while let Some(url_ch) = chars.next() {
+                for url_ch in chars.by_ref() {
                     if url_ch == ')' {
                         found_close_paren = true;
                         break;
@@ -368,7 +1995,7 @@ fn parse_image(text: &str) -> String {
             let mut alt_text = String::new();
             let mut found_close_bracket = false;
 
-            while let Some(inner_ch) = chars.next() {
+            for inner_ch in chars.by_ref() {
                 if inner_ch == ']' {
                     found_close_bracket = true;
                     break;
@@ -381,7 +2008,7 @@ fn parse_image(text: &str) -> String {
                 let mut url = String::new();
                 let mut found_close_paren = false;
 
-                while let Some(url_ch) = chars.next() {
+                for url_ch in chars.by_ref() {
                     if url_ch == ')' {
                         found_close_paren = true;
                         break;
@@ -419,7 +2046,7 @@ fn parse_image(text: &str) -> String {
     result
 }
 
-fn escape_html(text: &str) -> String {
+pub(crate) fn escape_html(text: &str) -> String {
     text.replace("&", "&amp;")
         .replace("<", "&lt;")
         .replace(">", "&gt;")
@@ -427,42 +2054,695 @@ fn escape_html(text: &str) -> String {
         .replace("'", "&#39;")
 }
 
-fn generate_post_page(tera: &Tera, post: &Post) -> String {
-    let mut context = Context::new();
+/// Strips tags from rendered HTML and collapses whitespace, leaving searchable plain text
+fn html_to_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+
+    for ch in html.chars() {
+        match ch {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            }
+            _ if !in_tag => text.push(ch),
+            _ => {}
+        }
+    }
+
+    let text = text
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Serializes JSON-LD for embedding in a `<script>` tag, escaping `</` so the content
+/// can never close the tag early
+fn to_json_ld(data: &serde_json::Value) -> String {
+    serde_json::to_string(data)
+        .unwrap_or_default()
+        .replace("</", "<\\/")
+}
+
+fn generate_page(tera: &Tera, base_context: &Context, page: &Page) -> String {
+    let mut context = base_context.clone();
+    context.insert("title", &page.title);
+    context.insert("slug", &page.slug);
+    context.insert("extra", &page.extra);
+    context.insert("content", &page.html_content);
+
+    render_page(
+        tera,
+        "page.html",
+        &context,
+        &page.source.display().to_string(),
+    )
+}
+
+fn hash_context(context: &Context, seed: u64) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    seed.hash(&mut hasher);
+    context.clone().into_json().to_string().hash(&mut hasher);
+    hasher.finish()
+}
+
+fn post_context(
+    base_context: &Context,
+    post: &Post,
+    series: Option<&Series>,
+    (previous, next): (Option<&Post>, Option<&Post>),
+    related: &[&Post],
+    translations: &[&Post],
+    images: &HashMap<String, ImageVariants>,
+) -> Context {
+    let mut context = base_context.clone();
+    let base_url = base_context
+        .get("base_url")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default();
     context.insert("title", &post.title);
+    context.insert("slug", &post.slug);
+    context.insert("path", &post.path);
+    context.insert("section", &post.section);
+    context.insert("url", &absolute_url(base_url, &post.path));
+    context.insert(
+        "cover_url",
+        &post.cover.as_ref().map(|c| absolute_url(base_url, c)),
+    );
     context.insert("date", &post.date);
-    context.insert("content", &post.html_content);
+    context.insert("updated", &post.updated);
+
+    let mut structured_data = serde_json::json!({
+        "@context": "https://schema.org",
+        "@type": "BlogPosting",
+        "headline": post.title,
+        "description": post.description.as_ref().unwrap_or(&post.excerpt),
+        "url": absolute_url(base_url, &post.path),
+        "datePublished": post.date,
+        "dateModified": post.updated,
+        "inLanguage": post.lang,
+        "keywords": post.keywords.iter().chain(&post.tags).collect::<Vec<_>>(),
+    });
+    if let Some(author) = post.extra.get("author") {
+        structured_data["author"] = serde_json::json!({ "@type": "Person", "name": author });
+    }
+    if let Some(cover) = &post.cover {
+        structured_data["image"] = absolute_url(base_url, cover).into();
+    }
+    context.insert("json_ld", &to_json_ld(&structured_data));
+    context.insert("excerpt", &post.excerpt);
+    context.insert("description", &post.description);
+    context.insert("keywords", &post.keywords);
+    context.insert("canonical_url", &post.canonical_url);
+    context.insert("lang", &post.lang);
+    context.insert("cover", &post.cover);
+    // Covers with resized variants are shown at medium size with a srcset of the rest
+    let cover_images = post.cover.as_ref().and_then(|cover| images.get(cover));
+    context.insert(
+        "cover_src",
+        &cover_images.map_or(post.cover.as_ref(), |v| Some(&v.medium.src)),
+    );
+    context.insert(
+        "cover_srcset",
+        &cover_images.and_then(ImageVariants::srcset),
+    );
+    context.insert(
+        "cover_webp_srcset",
+        &cover_images.and_then(ImageVariants::webp_srcset),
+    );
+    context.insert("tags", &post.tags);
+    let tag_feeds: Vec<_> = post
+        .tags
+        .iter()
+        .map(|tag| serde_json::json!({ "tag": tag, "url": tag_feed_path(tag) }))
+        .collect();
+    context.insert("tag_feeds", &tag_feeds);
+    context.insert("extra", &post.extra);
+    context.insert("word_count", &post.word_count);
+    context.insert("reading_time", &post.reading_time);
+    // Relative image paths point into a page bundle's own directory, otherwise at the site root
+    let base = if post.bundle.is_some() {
+        post_dir(&post.path)
+    } else {
+        "/"
+    };
+    context.insert(
+        "content",
+        &images::rewrite_images(&post.html_content, base, images),
+    );
+
+    // Series navigation: "Part N of X" with links to the neighbouring parts
+    let series_data = series.and_then(|s| {
+        let index = s.parts.iter().position(|p| p.slug == post.slug)?;
+        let link =
+            |p: &&Post| serde_json::json!({ "title": p.title, "slug": p.slug, "path": p.path });
+        Some(serde_json::json!({
+            "name": s.name,
+            "slug": s.slug,
+            "url": canonical_path(&format!("/series/{}/", s.slug)),
+            "part": index + 1,
+            "total": s.parts.len(),
+            "prev": index.checked_sub(1).and_then(|i| s.parts.get(i)).map(link),
+            "next": s.parts.get(index + 1).map(link),
+        }))
+    });
+    context.insert("series", &series_data);
+
+    // Chronologically adjacent posts in the same language
+    let link = |p: &Post| serde_json::json!({ "title": p.title, "slug": p.slug, "path": p.path });
+    context.insert("previous_post", &previous.map(link));
+    context.insert("next_post", &next.map(link));
+
+    let related_data: Vec<_> = related
+        .iter()
+        .map(|p| {
+            serde_json::json!({
+                "title": p.title,
+                "slug": p.slug,
+                "path": p.path,
+                "date": p.date,
+                "excerpt": p.excerpt,
+            })
+        })
+        .collect();
+    context.insert("related", &related_data);
+
+    let translations_data: Vec<_> = translations
+        .iter()
+        .map(|p| {
+            serde_json::json!({
+                "lang": p.lang,
+                "title": p.title,
+                "path": p.path,
+                "url": absolute_url(base_url, &p.path),
+            })
+        })
+        .collect();
+    context.insert("translations", &translations_data);
+    context.insert("index_url", &index_path(&post.lang));
+    context.insert("feed_base", &feed_base(&post.lang));
+
+    context
+}
+
+fn generate_post_page(tera: &Tera, context: &Context, post: &Post) -> Result<String, String> {
+    let template = section_template(tera, post.section.as_deref(), "post.html");
+    render(tera, &template, context, &post.source.display().to_string())
+}
 
-    match tera.render("post.html", &context) {
-        Ok(html) => html,
-        Err(e) => {
-            eprintln!("❌ Error rendering post template: {}", e);
-            String::new()
+fn generate_section_page(
+    tera: &Tera,
+    base_context: &Context,
+    section: &str,
+    posts: &[&Post],
+) -> String {
+    let mut context = base_context.clone();
+    context.insert("section", section);
+    context.insert("posts", posts);
+
+    let template = section_template(tera, Some(section), "list.html");
+    render_page(
+        tera,
+        &template,
+        &context,
+        &format!("section \"{}\"", section),
+    )
+}
+
+/// The template to use for a page in `section`: `<section>/<name>` if the site or theme
+/// has one, else the closest parent section's, else the default `name`. For example
+/// `rust/async/post.html`, then `rust/post.html`, then `post.html`
+fn section_template(tera: &Tera, section: Option<&str>, name: &str) -> String {
+    let mut dir = section;
+    while let Some(current) = dir {
+        let candidate = format!("{}/{}", current, name);
+        if tera.get_template_names().any(|t| t == candidate) {
+            return candidate;
         }
+        dir = current.rsplit_once('/').map(|(parent, _)| parent);
     }
+    name.to_string()
+}
+
+/// Renders a template, describing a failure with what was being rendered and every cause
+/// Tera gives, which names the template and the variable or filter that failed
+pub(crate) fn render(
+    tera: &Tera,
+    template: &str,
+    context: &Context,
+    what: &str,
+) -> Result<String, String> {
+    tera.render(template, context).map_err(|e| {
+        let mut message = format!("{}: {}", what, e);
+        let mut source = std::error::Error::source(&e);
+        while let Some(cause) = source {
+            message.push_str(&format!("\n  {}", cause));
+            source = cause.source();
+        }
+        message
+    })
+}
+
+/// Renders a template, or reports why it failed and returns an error page showing the
+/// problem in its place, so it's visible in the browser. Strict builds fail at the end
+fn render_page(tera: &Tera, template: &str, context: &Context, what: &str) -> String {
+    render(tera, template, context, what).unwrap_or_else(|message| {
+        report::error(message.clone());
+        generate_error_page(&message)
+    })
+}
+
+fn generate_error_page(message: &str) -> String {
+    format!(
+        r#"<!doctype html>
+<html lang="en">
+    <head>
+        <meta charset="UTF-8" />
+        <title>Build error</title>
+        <meta name="robots" content="noindex" />
+    </head>
+    <body style="font-family: sans-serif; max-width: 800px; margin: 2rem auto; padding: 0 20px;">
+        <h1 style="color: #b00020;">This page failed to build</h1>
+        <pre style="background: #fff0f0; padding: 1rem; border-radius: 4px; white-space: pre-wrap;">{}</pre>
+    </body>
+</html>
+"#,
+        escape_html(message)
+    )
+}
+
+fn generate_index_page(
+    tera: &Tera,
+    base_context: &Context,
+    lang: &str,
+    featured: &[&Post],
+    posts: &[&Post],
+    pagination: &serde_json::Value,
+    images: &HashMap<String, ImageVariants>,
+) -> String {
+    let mut context = base_context.clone();
+    // The whole post plus its cover's thumbnail variants
+    let to_data = |p: &&Post| {
+        let cover_images = p.cover.as_ref().and_then(|cover| images.get(cover));
+        let mut data = serde_json::to_value(p).unwrap_or_default();
+        data["thumbnail"] =
+            serde_json::json!(cover_images.map_or(p.cover.as_ref(), |v| Some(&v.thumbnail.src)));
+        data["thumbnail_srcset"] = serde_json::json!(cover_images.and_then(ImageVariants::srcset));
+        data["thumbnail_webp_srcset"] =
+            serde_json::json!(cover_images.and_then(ImageVariants::webp_srcset));
+        data
+    };
+
+    let featured_data: Vec<_> = featured.iter().map(to_data).collect();
+    let posts_data: Vec<_> = posts.iter().map(to_data).collect();
+
+    let base_url = base_context
+        .get("base_url")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default();
+    let site = base_context.get("site").cloned().unwrap_or_default();
+    let structured_data = serde_json::json!({
+        "@context": "https://schema.org",
+        "@type": "Blog",
+        "name": site["title"],
+        "description": site["description"],
+        "url": absolute_url(base_url, &index_path(lang)),
+        "blogPost": posts
+            .iter()
+            .chain(featured)
+            .map(|p| serde_json::json!({
+                "@type": "BlogPosting",
+                "headline": p.title,
+                "url": absolute_url(base_url, &p.path),
+                "datePublished": p.date,
+            }))
+            .collect::<Vec<_>>(),
+    });
+    context.insert("json_ld", &to_json_ld(&structured_data));
+
+    context.insert("featured_posts", &featured_data);
+    context.insert("posts", &posts_data);
+    context.insert("pagination", pagination);
+    context.insert("lang", lang);
+    context.insert("index_url", &index_path(lang));
+    context.insert("feed_base", &feed_base(lang));
+
+    render_page(
+        tera,
+        "index.html",
+        &context,
+        &format!("index page {}", pagination["current"]),
+    )
+}
+
+fn generate_series_page(tera: &Tera, base_context: &Context, series: &Series) -> String {
+    let mut context = base_context.clone();
+    context.insert("name", &series.name);
+    context.insert("parts", &series.parts);
+
+    render_page(
+        tera,
+        "series.html",
+        &context,
+        &format!("series \"{}\"", series.name),
+    )
+}
+
+fn generate_redirect_page(target: &str) -> String {
+    let target = escape_html(target);
+    format!(
+        r#"<!doctype html>
+<html lang="en">
+    <head>
+        <meta charset="UTF-8" />
+        <title>Redirecting…</title>
+        <link rel="canonical" href="{0}" />
+        <meta http-equiv="refresh" content="0; url={0}" />
+    </head>
+    <body><a href="{0}">This page has moved.</a></body>
+</html>
+"#,
+        target
+    )
+}
+
+/// One heading (a year or a month) on an archive page and the posts under it
+fn archive_group(label: String, url: String, posts: Vec<&Post>) -> serde_json::Value {
+    serde_json::json!({
+        "label": label,
+        "url": url,
+        "count": posts.len(),
+        "posts": posts,
+    })
 }
 
-fn generate_index_page(tera: &Tera, posts: &[Post]) -> String {
-    let mut context = Context::new();
-    let posts_data: Vec<_> = posts
+/// Post counts per year and month, newest first, with links to the archive pages
+fn archive_counts(archive: &BTreeMap<i32, BTreeMap<u32, Vec<&Post>>>) -> serde_json::Value {
+    let years: Vec<_> = archive
+        .iter()
+        .rev()
+        .map(|(year, months)| {
+            let months_data: Vec<_> = months
+                .iter()
+                .rev()
+                .map(|(month, posts)| {
+                    serde_json::json!({
+                        "month": month,
+                        "name": month_name(*month),
+                        "count": posts.len(),
+                        "url": canonical_path(&format!("/archive/{}/{:02}/", year, month)),
+                    })
+                })
+                .collect();
+            serde_json::json!({
+                "year": year,
+                "count": months.values().map(Vec::len).sum::<usize>(),
+                "url": canonical_path(&format!("/archive/{}/", year)),
+                "months": months_data,
+            })
+        })
+        .collect();
+    serde_json::Value::Array(years)
+}
+
+fn month_name(month: u32) -> String {
+    NaiveDate::from_ymd_opt(2000, month, 1)
+        .map(|d| d.format("%B").to_string())
+        .unwrap_or_default()
+}
+
+fn generate_archive_page(
+    tera: &Tera,
+    base_context: &Context,
+    heading: &str,
+    groups: &[serde_json::Value],
+) -> String {
+    let mut context = base_context.clone();
+    context.insert("heading", heading);
+    context.insert("groups", groups);
+
+    render_page(
+        tera,
+        "archive.html",
+        &context,
+        &format!("archive page \"{}\"", heading),
+    )
+}
+
+fn generate_search_index(posts: &[Post]) -> String {
+    let entries: Vec<_> = posts
         .iter()
         .map(|p| {
             serde_json::json!({
                 "title": p.title,
                 "slug": p.slug,
+                "path": p.path,
                 "date": p.date,
-                "excerpt": p.excerpt,
+                "tags": p.tags,
+                "content": html_to_text(&p.html_content),
             })
         })
         .collect();
 
-    context.insert("posts", &posts_data);
+    serde_json::to_string(&entries).unwrap_or_else(|_| "[]".to_string())
+}
+
+fn generate_not_found_page(tera: &Tera, base_context: &Context) -> String {
+    render_page(tera, "404.html", base_context, "404 page")
+}
+
+fn generate_search_page(tera: &Tera, base_context: &Context) -> String {
+    render_page(tera, "search.html", base_context, "search page")
+}
+
+/// `sitemap.xml` with every language's index, post and page. Indexes and translated posts
+/// list their versions in the other languages as `hreflang` alternates
+fn generate_sitemap(base_url: &str, languages: &[&str], posts: &[Post], pages: &[Page]) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(
+        "<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\" \
+         xmlns:xhtml=\"http://www.w3.org/1999/xhtml\">\n",
+    );
+    let alternates = |versions: &[(&str, String)]| -> String {
+        if versions.len() < 2 {
+            return String::new();
+        }
+        versions
+            .iter()
+            .map(|(lang, url)| {
+                format!(
+                    "<xhtml:link rel=\"alternate\" hreflang=\"{}\" href=\"{}\"/>",
+                    lang,
+                    escape_html(url)
+                )
+            })
+            .collect()
+    };
+
+    let indexes: Vec<(&str, String)> = languages
+        .iter()
+        .map(|lang| (*lang, absolute_url(base_url, &index_path(lang))))
+        .collect();
+    for (_, url) in &indexes {
+        xml.push_str(&format!(
+            "  <url><loc>{}</loc>{}</url>\n",
+            escape_html(url),
+            alternates(&indexes)
+        ));
+    }
+    for post in posts {
+        xml.push_str(&format!(
+            "  <url><loc>{}</loc>",
+            escape_html(&absolute_url(base_url, &post.path))
+        ));
+        if let Some(lastmod) = parse_date(&post.updated) {
+            xml.push_str(&format!(
+                "<lastmod>{}</lastmod>",
+                lastmod.format("%Y-%m-%d")
+            ));
+        }
+        let versions: Vec<(&str, String)> = posts
+            .iter()
+            .filter(|other| other.slug == post.slug)
+            .map(|other| (other.lang.as_str(), absolute_url(base_url, &other.path)))
+            .collect();
+        xml.push_str(&alternates(&versions));
+        xml.push_str("</url>\n");
+    }
+    for page in pages {
+        xml.push_str(&format!(
+            "  <url><loc>{}</loc></url>\n",
+            escape_html(&absolute_url(base_url, &page_path(page)))
+        ));
+    }
+
+    xml.push_str("</urlset>\n");
+    xml
+}
+
+/// `robots.txt` pointing crawlers at the sitemap, or keeping them out entirely
+fn generate_robots_txt(base_url: &str, disallow: bool) -> String {
+    if disallow {
+        return "User-agent: *\nDisallow: /\n".to_string();
+    }
+    format!(
+        "User-agent: *\nAllow: /\n\nSitemap: {}/sitemap.xml\n",
+        base_url
+    )
+}
+
+/// `humans.txt` (humanstxt.org) crediting the author and describing how the site is built.
+/// The last update is the newest post's, so the file only changes along with the content
+fn generate_humans_txt(options: &BuildOptions, languages: &[&str], posts: &[Post]) -> String {
+    let mut txt = String::new();
+    if let Some(author) = &options.site.author {
+        txt.push_str(&format!("/* TEAM */\nAuthor: {}\n\n", author));
+    }
+    txt.push_str("/* SITE */\n");
+    let last_update = posts
+        .iter()
+        .filter_map(|post| parse_date(&post.updated).or_else(|| parse_date(&post.date)))
+        .max();
+    if let Some(last_update) = last_update {
+        txt.push_str(&format!(
+            "Last update: {}\n",
+            last_update.format("%Y/%m/%d")
+        ));
+    }
+    txt.push_str(&format!("Language: {}\n", languages.join(", ")));
+    txt.push_str("Standards: HTML5, CSS3\n");
+    txt.push_str("Software: Rust, Tera, Actix Web\n");
+    txt
+}
+
+/// `security.txt` (RFC 9116). The RFC requires an expiry, which is kept under a year so
+/// each build renews it
+fn generate_security_txt(base_url: &str, contact: &str) -> String {
+    let expires = Local::now() + chrono::Duration::days(364);
+    format!(
+        "Contact: {}\nExpires: {}\nCanonical: {}/.well-known/security.txt\n",
+        contact,
+        expires.to_utc().format("%Y-%m-%dT%H:%M:%SZ"),
+        base_url
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_page_frontmatter_like_posts() {
+        let content =
+            "---\ntitle: \"About: me\"\nslug: 'about'\norder: 2\nlinks:\n  - a\n  - b\n---\nHello";
+        let page = parse_page(Path::new("pages/about.md"), content)
+            .unwrap()
+            .unwrap();
+        assert_eq!(page.title, "About: me");
+        assert_eq!(page.slug, "about");
+        assert_eq!(page.order, 2);
+        assert_eq!(page.extra["links"], "[a, b]");
+    }
 
-    match tera.render("index.html", &context) {
-        Ok(html) => html,
-        Err(e) => {
-            eprintln!("❌ Error rendering index template: {}", e);
-            String::new()
+    #[test]
+    fn reports_malformed_page_frontmatter_with_its_line() {
+        let content = "---\ntitle: About\nnot a field\norder: first\n---\n";
+        let Err(error) = parse_page(Path::new("pages/about.md"), content) else {
+            panic!("malformed frontmatter was accepted");
+        };
+        let message = error.to_string();
+        assert!(message.contains("pages/about.md:3: expected `key: value`"));
+        assert!(message.contains("pages/about.md:4: `order` must be a number"));
+    }
+
+    #[test]
+    fn rejects_series_parts_below_one() {
+        for part in ["0", "-1", "first"] {
+            let content = format!(
+                "---\ntitle: Hello\ndate: 2024-05-01\nseries: Intro\nseries_part: {}\n---\n",
+                part
+            );
+            let Err(error) = parse_post(Path::new("posts/hello.md"), &content) else {
+                panic!("series_part {} was accepted", part);
+            };
+            assert!(error
+                .to_string()
+                .contains("posts/hello.md:5: `series_part` must be a positive number"));
         }
+        let content = "---\ntitle: Hello\ndate: 2024-05-01\nseries_part: 1\n---\n";
+        let post = parse_post(Path::new("posts/hello.md"), content).unwrap();
+        assert_eq!(post.and_then(|post| post.series_part), Some(1));
+    }
+
+    #[test]
+    fn lists_every_language_index_in_the_sitemap() {
+        let sitemap = generate_sitemap("https://example.com", &["en", "id"], &[], &[]);
+        let alternates =
+            "<xhtml:link rel=\"alternate\" hreflang=\"en\" href=\"https://example.com/\"/>\
+            <xhtml:link rel=\"alternate\" hreflang=\"id\" href=\"https://example.com/id/\"/>";
+        assert!(sitemap.contains(&format!(
+            "<url><loc>https://example.com/</loc>{}</url>",
+            alternates
+        )));
+        assert!(sitemap.contains(&format!(
+            "<url><loc>https://example.com/id/</loc>{}</url>",
+            alternates
+        )));
+        // A single language has nothing to alternate with
+        let sitemap = generate_sitemap("https://example.com", &["en"], &[], &[]);
+        assert!(sitemap.contains("<url><loc>https://example.com/</loc></url>"));
+    }
+
+    #[test]
+    fn rebuilds_only_posts_whose_output_would_change() {
+        let base = std::env::temp_dir().join(format!("blog-rebuild-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        fs::create_dir_all(base.join("posts")).unwrap();
+        fs::write(
+            base.join("posts/hello.md"),
+            "---\ntitle: Hello\ndate: 2024-05-01\n---\nHi there",
+        )
+        .unwrap();
+        let dir = |name: &str| base.join(name).display().to_string();
+        let mut site = SiteConfig::default();
+        site.server.posts_dir = dir("posts");
+        site.server.output_dir = dir("output");
+        site.server.data_dir = dir("data");
+        site.server.themes_dir = dir("themes");
+        site.server.templates_dir = format!("{}/templates", env!("CARGO_MANIFEST_DIR"));
+        site.pages_dir = dir("pages");
+        site.static_dir = dir("static");
+        let mut options = BuildOptions {
+            include_future: false,
+            drafts: false,
+            base_url: "https://example.com".to_string(),
+            feed_limit: 20,
+            feed_full_content: false,
+            posts_per_page: 10,
+            minify: false,
+            webp: false,
+            permalink: "/:slug/".to_string(),
+            strict: false,
+            strict_links: false,
+            build_report: false,
+            precompress: false,
+            site,
+        };
+        let page = base.join("output/hello/index.html");
+
+        build_blog(&options).unwrap();
+        assert!(fs::read_to_string(&page).unwrap().contains("Hi there"));
+        // Left alone when nothing it shows changed
+        fs::write(&page, "from the last build").unwrap();
+        build_blog(&options).unwrap();
+        assert_eq!(fs::read_to_string(&page).unwrap(), "from the last build");
+        // Rendered again when an option changes how it's written
+        options.minify = true;
+        build_blog(&options).unwrap();
+        assert!(fs::read_to_string(&page).unwrap().contains("Hi there"));
     }
 }
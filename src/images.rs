@@ -0,0 +1,289 @@
+use crate::config::is_ignored;
+use crate::generator::track_output;
+use crate::report;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Width in pixels of the thumbnails shown in post listings
+const THUMBNAIL_WIDTH: u32 = 320;
+/// Width in pixels of images embedded in post bodies
+const MEDIUM_WIDTH: u32 = 800;
+/// Rendered width of content images, matching the 700px `.container` and its padding
+pub const SIZES: &str = "(max-width: 740px) calc(100vw - 40px), 700px";
+
+/// One generated image file, by site path
+#[derive(Clone)]
+pub struct ImageFile {
+    pub src: String,
+    pub width: u32,
+    /// WebP copy, only set when WebP output is enabled and it came out smaller
+    pub webp: Option<String>,
+}
+
+/// The resized copies of one image. Images already smaller than a variant's width use
+/// the original for that variant
+pub struct ImageVariants {
+    pub thumbnail: ImageFile,
+    pub medium: ImageFile,
+    pub full: ImageFile,
+}
+
+impl ImageVariants {
+    /// `srcset` listing every distinct size, or `None` when there's only the original
+    pub fn srcset(&self) -> Option<String> {
+        let candidates = self.candidates(|file| Some(&file.src));
+        (candidates.len() > 1).then(|| candidates.join(", "))
+    }
+
+    /// `srcset` of the WebP copies for a `<picture>` `<source>`, if there are any
+    pub fn webp_srcset(&self) -> Option<String> {
+        let candidates = self.candidates(|file| file.webp.as_ref());
+        (!candidates.is_empty()).then(|| candidates.join(", "))
+    }
+
+    fn candidates(&self, url: impl Fn(&ImageFile) -> Option<&String>) -> Vec<String> {
+        let mut candidates = Vec::new();
+        for file in [&self.thumbnail, &self.medium, &self.full] {
+            if let Some(url) = url(file) {
+                let candidate = format!("{} {}w", url, file.width);
+                if !candidates.contains(&candidate) {
+                    candidates.push(candidate);
+                }
+            }
+        }
+        candidates
+    }
+}
+
+/// Copies every image in `src` (including subfolders) to `dest` alongside `-thumb` and
+/// `-medium` resized variants, returning them keyed by the original's site path (e.g.
+/// `/images/2024/foo.png`). With `webp`, JPEG and PNG files also get a `.webp` copy
+/// (`foo.png.webp`). Files the `image` crate can't read (e.g. SVGs) are copied without
+/// variants, and the `ignore_files` ones not at all
+pub fn process_images(
+    src: &Path,
+    dest: &Path,
+    webp: bool,
+    ignore_files: &[String],
+) -> io::Result<HashMap<String, ImageVariants>> {
+    let mut variants = HashMap::new();
+    if src.exists() {
+        process_dir(src, dest, "/images", webp, ignore_files, &mut variants)?;
+    }
+    Ok(variants)
+}
+
+fn process_dir(
+    src: &Path,
+    dest: &Path,
+    url_prefix: &str,
+    webp: bool,
+    ignore_files: &[String],
+    variants: &mut HashMap<String, ImageVariants>,
+) -> io::Result<()> {
+    fs::create_dir_all(dest)?;
+    for entry in fs::read_dir(src)? {
+        let path = entry?.path();
+        if is_ignored(ignore_files, &path) {
+            continue;
+        }
+        if path.is_dir() {
+            let dir_name = path.file_name().unwrap().to_string_lossy().to_string();
+            process_dir(
+                &path,
+                &dest.join(&dir_name),
+                &format!("{}/{}", url_prefix, dir_name),
+                webp,
+                ignore_files,
+                variants,
+            )?;
+            continue;
+        }
+
+        let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+        let dest_path = dest.join(&file_name);
+        if !is_up_to_date(&path, &dest_path) {
+            fs::copy(&path, &dest_path)?;
+            println!("🖼️  Copied: {}", dest_path.display());
+        }
+        track_output(&dest_path);
+
+        let Ok((width, _)) = image::image_dimensions(&path) else {
+            continue;
+        };
+
+        let convertible = webp && is_jpeg_or_png(&file_name);
+        let image_file = |name: &str, width: u32| ImageFile {
+            src: format!("{}/{}", url_prefix, name),
+            width,
+            webp: convertible
+                .then(|| to_webp(&dest.join(name)))
+                .flatten()
+                .map(|webp_name| format!("{}/{}", url_prefix, webp_name)),
+        };
+
+        let full = image_file(&file_name, width);
+        let resized = |suffix: &str, target_width: u32| -> ImageFile {
+            if width <= target_width {
+                return full.clone();
+            }
+            let variant_name = variant_file_name(&file_name, suffix);
+            let variant_path = dest.join(&variant_name);
+            if !is_up_to_date(&path, &variant_path) {
+                if let Err(e) = resize(&path, &variant_path, target_width) {
+                    report::warn(format!("Could not resize {}: {}", path.display(), e));
+                    return full.clone();
+                }
+                println!("🖼️  Resized: {}", variant_path.display());
+            }
+            track_output(&variant_path);
+            image_file(&variant_name, target_width)
+        };
+
+        let thumbnail = resized("thumb", THUMBNAIL_WIDTH);
+        let medium = resized("medium", MEDIUM_WIDTH);
+        variants.insert(
+            full.src.clone(),
+            ImageVariants {
+                thumbnail,
+                medium,
+                full,
+            },
+        );
+    }
+
+    Ok(())
+}
+
+/// Points `<img>` tags in rendered post HTML at the medium variant with a `srcset` of
+/// every size, wrapped in a link to the full-size image. When WebP copies exist the image
+/// becomes a `<picture>` with the original as fallback. Relative paths are resolved against
+/// `base`, the directory URL they are relative to
+pub fn rewrite_images(html: &str, base: &str, variants: &HashMap<String, ImageVariants>) -> String {
+    const IMG_SRC: &str = "<img src=\"";
+
+    let mut result = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = rest.find(IMG_SRC) {
+        result.push_str(&rest[..start]);
+        let tag = &rest[start..];
+        // A tag without a closing quote is malformed, so copy the rest through unchanged
+        let Some(src_end) = tag[IMG_SRC.len()..].find('"').map(|i| IMG_SRC.len() + i) else {
+            rest = tag;
+            break;
+        };
+        let tag_end = tag[src_end..]
+            .find('>')
+            .map_or(tag.len(), |i| src_end + i + 1);
+
+        let src = &tag[IMG_SRC.len()..src_end];
+        let site_path = if src.starts_with('/') || src.starts_with('#') || src.contains(':') {
+            src.to_string()
+        } else {
+            format!("{}{}", base, src)
+        };
+
+        match variants.get(&site_path) {
+            Some(image) => {
+                let resized = image.medium.src != image.full.src;
+                let webp_srcset = image.webp_srcset();
+                if resized {
+                    result.push_str(&format!("<a href=\"{}\">", image.full.src));
+                }
+                if let Some(webp_srcset) = &webp_srcset {
+                    result.push_str(&format!(
+                        "<picture><source srcset=\"{}\" sizes=\"{}\" type=\"image/webp\" />",
+                        webp_srcset, SIZES
+                    ));
+                }
+                result.push_str(IMG_SRC);
+                result.push_str(&image.medium.src);
+                result.push('"');
+                if let Some(srcset) = image.srcset() {
+                    result.push_str(&format!(" srcset=\"{}\" sizes=\"{}\"", srcset, SIZES));
+                }
+                result.push_str(&tag[src_end + 1..tag_end]);
+                if webp_srcset.is_some() {
+                    result.push_str("</picture>");
+                }
+                if resized {
+                    result.push_str("</a>");
+                }
+            }
+            _ => {
+                result.push_str(IMG_SRC);
+                result.push_str(&site_path);
+                result.push_str(&tag[src_end..tag_end]);
+            }
+        }
+        rest = &tag[tag_end..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+/// `foo.png` with suffix `thumb` becomes `foo-thumb.png`
+fn variant_file_name(file_name: &str, suffix: &str) -> String {
+    match file_name.rsplit_once('.') {
+        Some((stem, extension)) => format!("{}-{}.{}", stem, suffix, extension),
+        None => format!("{}-{}", file_name, suffix),
+    }
+}
+
+fn is_jpeg_or_png(file_name: &str) -> bool {
+    let name = file_name.to_ascii_lowercase();
+    [".jpg", ".jpeg", ".png"]
+        .iter()
+        .any(|extension| name.ends_with(extension))
+}
+
+/// A derived file only needs regenerating when the original changed after it was written
+pub(crate) fn is_up_to_date(source: &Path, variant: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    match (modified(source), modified(variant)) {
+        (Some(source), Some(variant)) => variant >= source,
+        _ => false,
+    }
+}
+
+fn resize(source: &Path, dest: &Path, width: u32) -> io::Result<()> {
+    let img = image::open(source).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    // Height is unbounded so only the width constrains the resize
+    img.resize(width, u32::MAX, image::imageops::FilterType::Lanczos3)
+        .save(dest)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Writes a WebP copy next to `path` and returns its file name, the original's plus
+/// `.webp` so `foo.png` and `foo.jpg` don't share one. The encoder is lossless, which can
+/// come out larger than an already-compressed JPEG, so the copy is only used when it's
+/// actually smaller than the original
+fn to_webp(path: &Path) -> Option<String> {
+    let mut webp_path = path.as_os_str().to_owned();
+    webp_path.push(".webp");
+    let webp_path = PathBuf::from(webp_path);
+    if !is_up_to_date(path, &webp_path) {
+        let converted = image::open(path).and_then(|img| img.to_rgba8().save(&webp_path));
+        if let Err(e) = converted {
+            report::warn(format!(
+                "Could not convert {} to WebP: {}",
+                path.display(),
+                e
+            ));
+            return None;
+        }
+        println!("🖼️  Converted: {}", webp_path.display());
+    }
+    track_output(&webp_path);
+
+    let size = |path: &Path| fs::metadata(path).map(|m| m.len()).ok();
+    if size(&webp_path)? < size(path)? {
+        Some(webp_path.file_name()?.to_string_lossy().to_string())
+    } else {
+        None
+    }
+}
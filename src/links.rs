@@ -0,0 +1,106 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Attributes whose values point at other files of the site
+const LINK_ATTRIBUTES: &[&str] = &["href", "src", "srcset"];
+
+/// Scans generated pages for internal links and image sources that don't resolve to a file
+/// in `output_dir`, returning one `page: target` description per broken link
+pub fn check_links(output_dir: &Path, pages: &[PathBuf]) -> Vec<String> {
+    let mut broken = Vec::new();
+
+    for page in pages {
+        let Ok(html) = fs::read_to_string(page) else {
+            continue;
+        };
+        for target in link_targets(&html) {
+            if let Some(path) = internal_path(output_dir, page, &target) {
+                if !resolves(output_dir, &path) {
+                    broken.push(format!("{}: {}", page.display(), target));
+                }
+            }
+        }
+    }
+
+    broken
+}
+
+/// Values of every link attribute in the page, with `srcset` split into its URLs
+fn link_targets(html: &str) -> Vec<String> {
+    let mut targets = Vec::new();
+
+    for attribute in LINK_ATTRIBUTES {
+        let needle = format!(" {}=\"", attribute);
+        let mut rest = html;
+        while let Some(start) = rest.find(&needle) {
+            rest = &rest[start + needle.len()..];
+            let Some(end) = rest.find('"') else {
+                break;
+            };
+            let value = unescape(&rest[..end]);
+            if *attribute == "srcset" {
+                targets.extend(
+                    value
+                        .split(',')
+                        .filter_map(|candidate| candidate.split_whitespace().next())
+                        .map(str::to_string),
+                );
+            } else {
+                targets.push(value);
+            }
+            rest = &rest[end..];
+        }
+    }
+
+    targets
+}
+
+/// Site path a link points at, or `None` for external links, fragments and other schemes.
+/// Relative links are resolved against the directory of the page they appear on
+fn internal_path(output_dir: &Path, page: &Path, target: &str) -> Option<String> {
+    let target = target.split(['#', '?']).next().unwrap_or_default();
+    if target.is_empty() || target.starts_with("//") || target.contains(':') {
+        return None;
+    }
+    if target.starts_with('/') {
+        return Some(target.to_string());
+    }
+
+    let dir = page.parent()?.strip_prefix(output_dir).ok()?;
+    let mut segments: Vec<String> = dir
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect();
+    for segment in target.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment.to_string()),
+        }
+    }
+    let trailing_slash = if target.ends_with('/') { "/" } else { "" };
+    Some(format!("/{}{}", segments.join("/"), trailing_slash))
+}
+
+/// Whether the server (or a static host) can answer the path from the output directory
+fn resolves(output_dir: &Path, path: &str) -> bool {
+    let file = format!("{}{}", output_dir.display(), path);
+    let trimmed = file.trim_end_matches('/');
+    Path::new(&file).is_file()
+        || Path::new(&format!("{}/index.html", trimmed)).is_file()
+        || Path::new(&format!("{}.html", trimmed)).is_file()
+}
+
+/// Undoes the entity escaping Tera applies to attribute values
+fn unescape(value: &str) -> String {
+    value
+        .replace("&#x2F;", "/")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
@@ -0,0 +1,110 @@
+use actix_web::{web, HttpResponse};
+use futures_util::{stream, StreamExt};
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// Server-sent events stream the injected script listens to
+pub const ENDPOINT: &str = "/__livereload";
+
+/// Reloads the page on `reload` events and shows `build-error` ones over it.
+/// `EventSource` reconnects on its own after the server restarts
+const SCRIPT: &str = r#"<script>
+(() => {
+    const source = new EventSource("/__livereload");
+    source.onmessage = (event) => {
+        if (event.data === "reload") location.reload();
+    };
+    source.addEventListener("build-error", (event) => {
+        let overlay = document.getElementById("__build-error");
+        if (!overlay) {
+            overlay = document.body.appendChild(document.createElement("div"));
+            overlay.id = "__build-error";
+            overlay.title = "Click to dismiss";
+            overlay.style.cssText = "position: fixed; inset: 0; z-index: 99999; overflow: auto; padding: 2rem; background: rgba(20, 20, 20, 0.92); color: #ffb4b4; font: 14px/1.5 monospace; white-space: pre-wrap; cursor: pointer;";
+            overlay.onclick = () => overlay.remove();
+        }
+        overlay.textContent = "❌ Build failed, showing the last good version\n\n" + event.data;
+    });
+})();
+</script>"#;
+
+/// Tells open pages to reload after a rebuild, or shows them why it failed, over
+/// server-sent events
+#[derive(Clone)]
+pub struct LiveReload {
+    /// Events to send, or `None` to end every stream when the server shuts down
+    sender: broadcast::Sender<Option<String>>,
+    /// Problems from the latest build, shown to pages as soon as they connect
+    problems: Arc<Mutex<Option<String>>>,
+}
+
+impl LiveReload {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(16);
+        LiveReload {
+            sender,
+            problems: Arc::default(),
+        }
+    }
+
+    /// Reloads every connected page after a build. `problems` the build ran into (e.g. a
+    /// post that was skipped) are shown once the reloaded pages reconnect
+    pub fn reload(&self, problems: Option<String>) {
+        *self.problems.lock().unwrap() = problems;
+        let _ = self.sender.send(Some("data: reload\n\n".to_string()));
+    }
+
+    /// Shows a failed build's error over every connected page, which keep their content
+    pub fn build_failed(&self, error: &str) {
+        *self.problems.lock().unwrap() = Some(error.to_string());
+        let _ = self.sender.send(Some(error_event(error)));
+    }
+
+    /// Ends every open stream, which a graceful shutdown would otherwise wait on
+    pub fn close(&self) {
+        let _ = self.sender.send(None);
+    }
+
+    /// Response for `ENDPOINT`, which stays open and sends an event per notification
+    pub fn events(&self) -> HttpResponse {
+        let current = self.problems.lock().unwrap().as_deref().map(error_event);
+        let updates = stream::unfold(self.sender.subscribe(), |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(Some(event)) => return Some((event, receiver)),
+                    Err(RecvError::Lagged(_)) => continue,
+                    Ok(None) | Err(RecvError::Closed) => return None,
+                }
+            }
+        });
+        let events = stream::iter(current)
+            .chain(updates)
+            .map(|event| Ok::<_, actix_web::Error>(web::Bytes::from(event)));
+        HttpResponse::Ok()
+            .content_type("text/event-stream")
+            .insert_header(("Cache-Control", "no-cache"))
+            // Compression would hold events back until enough of them fill a block
+            .insert_header(("Content-Encoding", "identity"))
+            .streaming(events)
+    }
+}
+
+/// A `build-error` event, with one `data:` field per line as the format requires
+fn error_event(error: &str) -> String {
+    let mut event = String::from("event: build-error\n");
+    for line in error.lines() {
+        event.push_str(&format!("data: {}\n", line));
+    }
+    event.push('\n');
+    event
+}
+
+/// Adds the script that listens for reloads to an HTML page, before `</body>` or at the end
+pub fn inject_script(html: &[u8]) -> Vec<u8> {
+    let html = String::from_utf8_lossy(html);
+    match html.rfind("</body>") {
+        Some(end) => format!("{}{}{}", &html[..end], SCRIPT, &html[end..]),
+        None => format!("{}{}", html, SCRIPT),
+    }
+    .into_bytes()
+}
@@ -1,107 +1,1313 @@
+use actix_files::NamedFile;
+use actix_web::body::{BodySize, EitherBody, MessageBody, SizedStream};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::HttpDate;
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::Next;
 use actix_web::{middleware, web, App, HttpRequest, HttpResponse, HttpServer};
+use base64::prelude::{Engine, BASE64_STANDARD};
 use notify::{RecommendedWatcher, RecursiveMode, Watcher};
-use std::path::Path;
-use std::sync::mpsc;
+use percent_encoding::percent_decode_str;
+use std::collections::BTreeMap;
+use std::net::{IpAddr, TcpListener, UdpSocket};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant, SystemTime};
 
+mod admin;
+mod api;
+mod cache;
+mod changes;
+mod compress;
+mod config;
+mod data;
+mod feed;
+mod filters;
+mod functions;
 mod generator;
+mod images;
+mod links;
+mod livereload;
+mod minify;
+mod qr;
+mod ratelimit;
+mod report;
+mod shortcodes;
+mod status;
+mod tls;
+
+/// Methods the server answers, as listed in `Allow` headers
+const ALLOWED_METHODS: &str = "GET, HEAD, OPTIONS";
+
+/// How long the sources must stay unchanged before a rebuild starts, so an editor's
+/// several writes of one save, or many files saved at once, make a single rebuild
+const REBUILD_DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Rebuilds the site when posted to with the `webhook_token`, e.g. from a git hook
+const HOOK_ENDPOINT: &str = "/__hooks/rebuild";
+
+/// What request handlers need to know about the site being served
+struct ServerState {
+    output_dir: PathBuf,
+    /// Set when pages should reload themselves after rebuilds
+    live_reload: Option<livereload::LiveReload>,
+    /// Whether text responses are compressed on the fly
+    compress: bool,
+    /// Whether page URLs end with a slash, which the other form is redirected to
+    trailing_slash: bool,
+    /// `Cache-Control` values for matching files, outside of dev
+    cache_rules: Vec<config::CacheRule>,
+    /// `Content-Type` values by file extension, over the guessed ones
+    content_types: BTreeMap<String, String>,
+    /// The latest build, when files are sent from memory
+    output_cache: Option<cache::OutputCache>,
+    /// `user:password` visitors must give, if any
+    basic_auth: Option<String>,
+    /// Set when clients may only make so many requests a minute
+    rate_limiter: Option<ratelimit::RateLimiter>,
+    max_uri_length: usize,
+    max_request_size: u64,
+    /// Token `HOOK_ENDPOINT` requires, which is disabled without one
+    webhook_token: Option<String>,
+    /// Token the posts API and admin previews require, which lets them past `basic_auth`
+    admin_token: Option<String>,
+    /// Asks the rebuild thread for a build, of the files sent or else of everything
+    rebuild: mpsc::Sender<Vec<PathBuf>>,
+    build_status: status::BuildStatus,
+}
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    let started_at = Instant::now();
+    let site = match load_site() {
+        Ok(site) => site,
+        Err(e) => {
+            eprintln!("❌ Error reading config: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Bound before building so the local base URL has the port actually in use
+    let listener = match bind(&site) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("❌ Could not start the server: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let address = listener.local_addr()?;
+    let port = address.port().to_string();
+    let options = build_options(site, &port);
+
     // Initial build
-    println!("🚀 Building blog...");
-    generator::build_blog()?;
+    println!("🚀 Building blog ({} profile)...", options.site.profile);
+    let build_status = status::BuildStatus::default();
+    let started = Instant::now();
+    let summary = match generator::build_blog(&options) {
+        Ok(summary) => summary,
+        Err(e) => {
+            eprintln!("❌ Error building blog:\n{}", e);
+            std::process::exit(1);
+        }
+    };
+    build_status.succeeded(started.elapsed(), summary.posts, &report::errors());
     println!("✅ Blog built successfully!\n");
 
     // Create a channel for file change notifications
     let (tx, rx) = mpsc::channel();
+    let rebuild = tx.clone();
 
-    // Spawn watcher thread
-    std::thread::spawn(move || {
-        if let Err(e) = setup_watcher(tx) {
+    // Watch the sources; the watcher reports changes from its own thread until it's dropped
+    let mut watched = vec![
+        PathBuf::from(&options.site.server.posts_dir),
+        PathBuf::from(&options.site.pages_dir),
+        PathBuf::from(&options.site.server.data_dir),
+        PathBuf::from(config::CONFIG_FILE),
+    ];
+    watched.extend(generator::template_dirs(&options.site));
+    watched.extend(generator::static_dirs(&options.site));
+    let watcher = match setup_watcher(tx, &watched, options.site.server.ignore_files.clone()) {
+        Ok(watcher) => Some(watcher),
+        Err(e) => {
             eprintln!("Failed to setup file watcher: {}", e);
+            None
         }
-    });
+    };
 
-    // Get port from environment variable or default to 8000
-    let port = std::env::var("PORT").unwrap_or_else(|_| "8000".to_string());
-    let bind_address = format!("0.0.0.0:{}", port);
+    let tls_config = if options.site.server.https {
+        match tls::server_config(&options.site, lan_address()) {
+            Ok(config) => Some(config),
+            Err(e) => {
+                eprintln!("❌ Could not set up HTTPS: {}", e);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+    let scheme = scheme(&options.site);
 
     println!("Starting web server...");
-    println!("Server listening on: {}", bind_address);
-
-    // Spawn a thread to handle file change events
-    std::thread::spawn(move || {
-        for _ in rx.iter() {
-            println!("\n📝 Changes detected! Rebuilding blog...");
-            if let Err(e) = generator::build_blog() {
-                println!("❌ Error rebuilding blog: {}", e);
-            } else {
-                println!("✅ Blog rebuilt successfully!");
+    println!("Server listening on: {}://{}", scheme, address);
+    if std::env::args().any(|arg| arg == "--lan") {
+        match lan_address() {
+            Some(ip) => {
+                let url = format!("{}://{}:{}/", scheme, ip, address.port());
+                println!("📱 On your network: {}", url);
+                if let Some(code) = qr::render(&url) {
+                    print!("{}", code);
+                }
+            }
+            None => println!("⚠️  Couldn't find this machine's address on the network"),
+        }
+    }
+    if options.site.server.basic_auth.is_some() {
+        println!("🔒 Visitors need the basic_auth user name and password");
+    }
+
+    let live_reload = options
+        .site
+        .server
+        .live_reload
+        .then(livereload::LiveReload::new);
+    let output_cache = if options.site.server.memory_cache {
+        match cache::OutputCache::new(Path::new(&options.site.server.output_dir)) {
+            Ok(cache) => Some(cache),
+            Err(e) => {
+                eprintln!("❌ Error reading the build into memory: {}", e);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+
+    // Spawn a thread to handle file change events and scheduled posts becoming due. It
+    // returns how many rebuilds it did and how many of them failed once every sender is gone
+    let mut build_options = options.clone();
+    let rebuild_live_reload = live_reload.clone();
+    let rebuild_output_cache = output_cache.clone();
+    let rebuild_port = port.clone();
+    let rebuild_status = build_status.clone();
+    let rebuilder = std::thread::spawn(move || {
+        let (mut rebuilds, mut failures) = (0, 0);
+        let mut next_scheduled = summary.next_scheduled;
+        let mut config_modified = modified_time(Path::new(config::CONFIG_FILE));
+        let mut templates_modified = generator::templates_modified(&build_options.site);
+        let mut templates_broken = false;
+        loop {
+            let event = match next_scheduled {
+                Some(publish_at) => {
+                    let wait = (publish_at - chrono::Local::now().naive_local())
+                        .to_std()
+                        .unwrap_or(Duration::ZERO);
+                    rx.recv_timeout(wait)
+                }
+                None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+
+            let mut changes = changes::Changes::default();
+            match event {
+                Ok(paths) => {
+                    changes.add(paths);
+                    while let Ok(paths) = rx.recv_timeout(REBUILD_DEBOUNCE) {
+                        changes.add(paths);
+                    }
+                    println!("\n📝 Changes in {}! Rebuilding blog...", changes)
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    println!("\n⏰ Scheduled post is due! Rebuilding blog...")
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+
+            let modified = modified_time(Path::new(config::CONFIG_FILE));
+            if changes.touches(Path::new(config::CONFIG_FILE)) && modified != config_modified {
+                config_modified = modified;
+                build_options = reload_options(&build_options, &rebuild_port);
+            }
+            // Broken templates are tried again on every rebuild until they load
+            let mut template_error = None;
+            let templates_changed = generator::template_dirs(&build_options.site)
+                .iter()
+                .any(|dir| changes.touches(dir));
+            let modified = generator::templates_modified(&build_options.site);
+            if (templates_changed || templates_broken) && modified != templates_modified {
+                match generator::reload_templates(&build_options.site) {
+                    Ok(()) => {
+                        templates_modified = modified;
+                        println!("🎨 Reloaded templates");
+                    }
+                    Err(e) => {
+                        println!(
+                            "❌ Error reloading templates, keeping the previous ones: {}",
+                            e
+                        );
+                        template_error = Some(e.to_string());
+                    }
+                }
+            }
+            templates_broken = template_error.is_some();
+
+            let started = Instant::now();
+            rebuilds += 1;
+            match generator::build_blog(&build_options) {
+                Ok(summary) => {
+                    next_scheduled = summary.next_scheduled;
+                    let problems: Vec<String> =
+                        template_error.into_iter().chain(report::errors()).collect();
+                    rebuild_status.succeeded(started.elapsed(), summary.posts, &problems);
+                    if let Some(output_cache) = &rebuild_output_cache {
+                        if let Err(e) = output_cache.refresh() {
+                            println!("❌ Error reading the build into memory: {}", e);
+                        }
+                    }
+                    println!("✅ Blog rebuilt successfully!");
+                    if let Some(live_reload) = &rebuild_live_reload {
+                        live_reload.reload((!problems.is_empty()).then(|| problems.join("\n")));
+                    }
+                }
+                Err(e) => {
+                    println!("❌ Error rebuilding blog: {}", e);
+                    failures += 1;
+                    rebuild_status.failed(&e.to_string());
+                    if let Some(live_reload) = &rebuild_live_reload {
+                        live_reload.build_failed(&e.to_string());
+                    }
+                }
             }
         }
+        (rebuilds, failures)
     });
 
+    if options.site.server.admin && options.site.server.admin_token.is_none() {
+        println!("⚠️  Not serving {} without an admin_token", admin::ENDPOINT);
+    }
+    let posts_api =
+        api::PostsApi::configured(&options.site.server, rebuild.clone()).map(web::Data::new);
+    let shutdown_live_reload = live_reload.clone();
+    let state = web::Data::new(ServerState {
+        output_dir: PathBuf::from(&options.site.server.output_dir),
+        live_reload,
+        compress: options.site.server.compress,
+        trailing_slash: options.site.server.trailing_slash != "never",
+        cache_rules: options.site.server.cache_rules.clone(),
+        content_types: options.site.server.content_types.clone(),
+        output_cache,
+        basic_auth: options.site.server.basic_auth.clone(),
+        rate_limiter: (options.site.server.rate_limit > 0)
+            .then(|| ratelimit::RateLimiter::new(options.site.server.rate_limit)),
+        max_uri_length: options.site.server.max_uri_length,
+        max_request_size: options.site.server.max_request_size,
+        webhook_token: options.site.server.webhook_token.clone(),
+        admin_token: options.site.server.admin_token.clone(),
+        rebuild,
+        build_status,
+    });
+
+    // `--verbose` prints every request, e.g. to see what the browser asks for
+    let log_requests = std::env::args().any(|arg| arg == "--verbose" || arg == "-v");
+    let security_headers = options.site.server.security_headers.headers();
+
     println!("Server started! Ready to serve your blog.\n");
-    HttpServer::new(|| {
+    let server = HttpServer::new(move || {
         App::new()
-            .wrap(middleware::NormalizePath::trim())
+            .app_data(state.clone())
+            .wrap(middleware::Condition::new(
+                state.compress,
+                middleware::Compress::default(),
+            ))
+            .wrap(middleware::from_fn(redirect_trailing_slash))
+            .wrap(
+                security_headers
+                    .iter()
+                    .fold(middleware::DefaultHeaders::new(), |headers, header| {
+                        headers.add(header.clone())
+                    }),
+            )
+            .wrap(middleware::from_fn(check_credentials))
+            .wrap(middleware::from_fn(guard_requests))
+            .wrap(middleware::Condition::new(
+                log_requests,
+                middleware::from_fn(log_request),
+            ))
+            .route(livereload::ENDPOINT, web::get().to(live_reload_events))
+            .route(HOOK_ENDPOINT, web::post().to(rebuild_hook))
+            .route(status::STATUS_ENDPOINT, web::get().to(report_status))
+            .route(status::HEALTH_ENDPOINT, web::get().to(health))
+            .configure(|config| {
+                if let Some(posts_api) = &posts_api {
+                    api::routes(config, posts_api.clone());
+                    admin::routes(config, posts_api.clone());
+                }
+            })
             .default_service(web::route().to(handle_request))
-    })
-    .bind(&bind_address)?
-    .run()
-    .await
+    });
+    let server = match tls_config {
+        Some(tls_config) => server.listen_rustls_0_23(listener, tls_config)?,
+        None => server.listen(listener)?,
+    }
+    .disable_signals()
+    .run();
+
+    // Ctrl+C and SIGTERM let open requests finish. Live reload streams never do, so
+    // they're ended first
+    let handle = server.handle();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        println!("\n🛑 Shutting down...");
+        if let Some(live_reload) = &shutdown_live_reload {
+            live_reload.close();
+        }
+        handle.stop(true).await;
+    });
+    // `--open` shows the site in the browser, which waits for the server to start answering
+    if std::env::args().any(|arg| arg == "--open") {
+        let url = format!("{}://{}:{}/", scheme, local_host(), port);
+        if let Err(e) = open_browser(&url) {
+            println!("⚠️  Couldn't open a browser at {}: {}", url, e);
+        }
+    }
+    server.await?;
+
+    // Stopping the server dropped its senders. Without the watcher's, the rebuild thread
+    // finishes a rebuild in progress and exits
+    drop(watcher);
+    let (rebuilds, failures) = rebuilder.join().unwrap_or_default();
+    println!(
+        "👋 Stopped after {}, with {} rebuilds ({} failed)",
+        format_uptime(started_at.elapsed()),
+        rebuilds,
+        failures
+    );
+    Ok(())
+}
+
+/// Opens `url` in the default browser, with the command each platform has for it
+fn open_browser(url: &str) -> std::io::Result<()> {
+    let mut command = if cfg!(target_os = "windows") {
+        let mut command = std::process::Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else if cfg!(target_os = "macos") {
+        std::process::Command::new("open")
+    } else {
+        std::process::Command::new("xdg-open")
+    };
+    command
+        .arg(url)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()?;
+    Ok(())
+}
+
+/// Waits for Ctrl+C, or SIGTERM from a supervisor like `docker stop`
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    if let Ok(mut terminate) =
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+    {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+        return;
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// A duration like `1h 5m` or `42s`
+fn format_uptime(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        3600.. => format!("{}h {}m", seconds / 3600, seconds % 3600 / 60),
+        60.. => format!("{}m {}s", seconds / 60, seconds % 60),
+        _ => format!("{}s", seconds),
+    }
+}
+
+/// Reads the config file for the selected build profile and applies the command line and
+/// environment overrides to the server address
+fn load_site() -> std::io::Result<config::SiteConfig> {
+    // Build profile: `--env <name>`, `--production`, or the BLOG_ENV environment variable
+    let profile = flag_value("--env")
+        .or_else(|| {
+            std::env::args()
+                .any(|arg| arg == "--production")
+                .then(|| "production".to_string())
+        })
+        .or_else(|| std::env::var("BLOG_ENV").ok())
+        .unwrap_or_else(|| "dev".to_string());
+    let mut site = config::load(Path::new(config::CONFIG_FILE), &profile)?;
+
+    if let Some(host) = flag_value("--host").or_else(|| std::env::var("HOST").ok()) {
+        site.server.host = host;
+    }
+    if let Some(port) = flag_value("--port").or_else(|| std::env::var("PORT").ok()) {
+        site.server.port = port.parse().map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("invalid port \"{}\"", port),
+            )
+        })?;
+    }
+    // `--lan` serves other devices on the network, like a phone
+    if std::env::args().any(|arg| arg == "--lan") {
+        site.server.host = "0.0.0.0".to_string();
+    }
+    if let Ok(credentials) = std::env::var("BASIC_AUTH") {
+        site.server.basic_auth = Some(credentials);
+    }
+    if let Ok(token) = std::env::var("WEBHOOK_TOKEN") {
+        site.server.webhook_token = Some(token);
+    }
+    if let Ok(token) = std::env::var("ADMIN_TOKEN") {
+        site.server.admin_token = Some(token);
+    }
+    Ok(site)
+}
+
+/// Opens the server's socket on the configured host and port. When the port is taken the
+/// next free one is used instead, unless `--strict-port` is given
+fn bind(site: &config::SiteConfig) -> std::io::Result<TcpListener> {
+    let strict = std::env::args().any(|arg| arg == "--strict-port");
+    let host = site.server.host.as_str();
+    match TcpListener::bind((host, site.server.port)) {
+        Err(e) if e.kind() == std::io::ErrorKind::AddrInUse && !strict && site.server.port != 0 => {
+            let listener = (site.server.port.saturating_add(1)
+                ..=site.server.port.saturating_add(20))
+                .find_map(|port| TcpListener::bind((host, port)).ok())
+                .map_or_else(|| TcpListener::bind((host, 0)), Ok)?;
+            println!(
+                "⚠️  Port {} is in use, using {} instead",
+                site.server.port,
+                listener.local_addr()?.port()
+            );
+            Ok(listener)
+        }
+        Err(e) => Err(std::io::Error::new(
+            e.kind(),
+            format!("{}:{}: {}", host, site.server.port, e),
+        )),
+        listener => listener,
+    }
+}
+
+/// This machine's address on the local network, the one it reaches other networks from.
+/// Connecting a UDP socket picks a route without sending anything
+fn lan_address() -> Option<IpAddr> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("192.0.2.1:80").ok()?;
+    Some(socket.local_addr().ok()?.ip()).filter(|ip| !ip.is_loopback() && !ip.is_unspecified())
+}
+
+/// Host of the local server's URL: its network address with `--lan`, so links in pages
+/// work from other devices
+fn local_host() -> String {
+    let lan = std::env::args().any(|arg| arg == "--lan");
+    lan.then(lan_address)
+        .flatten()
+        .map_or_else(|| "localhost".to_string(), |ip| ip.to_string())
+}
+
+/// Scheme of the local server's URL
+fn scheme(site: &config::SiteConfig) -> &'static str {
+    if site.server.https {
+        "https"
+    } else {
+        "http"
+    }
+}
+
+/// Build settings from the site config, applying the command line and environment
+/// overrides. `port` is the one the server is listening on
+fn build_options(mut site: config::SiteConfig, port: &str) -> generator::BuildOptions {
+    // The command line and environment variables override the config file, e.g. for
+    // preview deployments served from another host
+    let base_url = flag_value("--base-url")
+        .or_else(|| std::env::var("BASE_URL").ok())
+        .or_else(|| Some(site.base_url.clone()).filter(|url| !url.is_empty()))
+        .unwrap_or_else(|| format!("{}://{}:{}", scheme(&site), local_host(), port));
+    site.base_url = base_url.trim_end_matches('/').to_string();
+    if let Ok(author) = std::env::var("AUTHOR") {
+        site.author = Some(author);
+    }
+    if let Ok(contact) = std::env::var("SECURITY_CONTACT") {
+        site.security_contact = Some(contact);
+    }
+
+    generator::BuildOptions {
+        include_future: site.future || std::env::args().any(|arg| arg == "--future"),
+        drafts: site.drafts,
+        base_url: site.base_url.clone(),
+        feed_limit: site.feed_limit,
+        feed_full_content: site.feed_full_content,
+        posts_per_page: site.posts_per_page,
+        minify: site.minify,
+        webp: site.webp || std::env::args().any(|arg| arg == "--webp"),
+        permalink: site.permalink.clone(),
+        strict: std::env::args().any(|arg| arg == "--strict"),
+        strict_links: std::env::args().any(|arg| arg == "--strict" || arg == "--strict-links"),
+        build_report: std::env::args().any(|arg| arg == "--build-report"),
+        precompress: std::env::args().any(|arg| arg == "--precompress"),
+        site,
+    }
+}
+
+/// Describes how the restart-only settings differ between `running` and `configured`, with
+/// their new values. Secrets aren't serialized, so they're only mentioned when nothing else
+/// differs
+fn restart_changes(
+    running: &config::ServerSettings,
+    configured: &config::ServerSettings,
+) -> Vec<String> {
+    let table = |settings| toml::Table::try_from(settings).unwrap_or_default();
+    let (running_table, configured_table) = (table(running), table(configured));
+    let mut changes: Vec<String> = configured_table
+        .iter()
+        .filter(|(name, value)| running_table.get(*name) != Some(value))
+        .map(|(name, value)| format!("{} changed to {}", name, value))
+        .chain(
+            running_table
+                .keys()
+                .filter(|name| !configured_table.contains_key(*name))
+                .map(|name| format!("{} was unset", name)),
+        )
+        .collect();
+    if changes.is_empty() {
+        changes.push("basic_auth or a token changed".to_string());
+    }
+    changes
+}
+
+/// Reloads the config file for the next rebuild if it changed since `current` was loaded.
+/// Settings that need a restart keep their running values, with a notice saying so
+fn reload_options(current: &generator::BuildOptions, port: &str) -> generator::BuildOptions {
+    let mut options = match load_site() {
+        Ok(site) => build_options(site, port),
+        Err(e) => {
+            println!(
+                "❌ Error reloading config, keeping the previous settings: {}",
+                e
+            );
+            return current.clone();
+        }
+    };
+    if options.site.server != current.site.server {
+        for change in restart_changes(&current.site.server, &options.site.server) {
+            println!("⚠️  {}, restart the server to apply it", change);
+        }
+        options.site.server = current.site.server.clone();
+    }
+    println!("⚙️  Reloaded {}", config::CONFIG_FILE);
+    options
+}
+
+/// Prints a request's method, path, status, how long it took and the size of the response,
+/// as sent after any compression
+async fn log_request(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let start = Instant::now();
+    let (method, path) = (req.method().clone(), req.path().to_string());
+    let response = next.call(req).await?;
+    let size = match response.response().body().size() {
+        BodySize::None => "-".to_string(),
+        BodySize::Sized(bytes) => report::format_size(bytes),
+        BodySize::Stream => "streamed".to_string(),
+    };
+    println!(
+        "{} {} {} {:.1?} {}",
+        method,
+        path,
+        response.status().as_u16(),
+        start.elapsed(),
+        size
+    );
+    Ok(response)
+}
+
+/// The form of `path` the redirect goes to: with a trailing slash or without one for pages,
+/// depending on `trailing_slash`, and without one for files, with repeated slashes merged so
+/// `//example.com/` can't redirect off the site. Paths `resolve` finds no file for are kept
+/// as they are
+fn preferred_path(
+    path: &str,
+    trailing_slash: bool,
+    resolve: impl Fn(&str) -> Option<PathBuf>,
+) -> String {
+    let decoded = percent_decode_str(path).decode_utf8_lossy();
+    let file_path = decoded.trim_matches('/');
+    let segments: Vec<&str> = path.split('/').filter(|part| !part.is_empty()).collect();
+    let trimmed = format!("/{}", segments.join("/"));
+    if resolve(&format!("{}/index.html", file_path)).is_some() {
+        if trailing_slash {
+            format!("{}/", trimmed)
+        } else {
+            trimmed.to_string()
+        }
+    } else if path.ends_with('/') && resolve(file_path).is_some() {
+        trimmed.to_string()
+    } else {
+        path.to_string()
+    }
+}
+
+/// Redirects page URLs to the form the `trailing_slash` setting asks for, e.g. `/hello` to
+/// `/hello/`, and file URLs to the form without one. The server's own endpoints and paths
+/// that don't exist are left alone
+async fn redirect_trailing_slash(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let path = req.path().to_string();
+    let page_request = matches!(*req.method(), Method::GET | Method::HEAD)
+        && path != "/"
+        && !path.starts_with("/__");
+    let state = req.app_data::<web::Data<ServerState>>().cloned();
+    let (Some(state), true) = (state, page_request) else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+
+    let trailing_slash = state.trailing_slash;
+    let canonical = match state.output_cache.as_ref().map(cache::OutputCache::files) {
+        Some(files) => preferred_path(&path, trailing_slash, |path| files.resolve(path)),
+        // Looking on disk canonicalizes paths, which blocks
+        None => {
+            let (output_dir, path) = (state.output_dir.clone(), path.clone());
+            web::block(move || {
+                preferred_path(&path, trailing_slash, |path| {
+                    resolve_path(&output_dir, path)
+                })
+            })
+            .await?
+        }
+    };
+    if canonical == path || canonical.is_empty() {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+
+    let location = match req.query_string() {
+        "" => canonical,
+        query => format!("{}?{}", canonical, query),
+    };
+    let response = HttpResponse::MovedPermanently()
+        .insert_header(("Location", location))
+        .finish();
+    Ok(req.into_response(response).map_into_right_body())
+}
+
+/// Turns away requests over the `rate_limit` and ones with a URI or body above the limits,
+/// before they're looked at any further
+async fn guard_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let Some(state) = req.app_data::<web::Data<ServerState>>().cloned() else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+    if let (Some(limiter), Some(peer)) = (&state.rate_limiter, req.peer_addr()) {
+        if let Err(wait) = limiter.check(peer.ip()) {
+            let response = HttpResponse::TooManyRequests()
+                .insert_header(("Retry-After", wait.as_secs().max(1).to_string()))
+                .body("429 Too Many Requests");
+            return Ok(req.into_response(response).map_into_right_body());
+        }
+    }
+    let uri_length = req
+        .uri()
+        .path_and_query()
+        .map_or(0, |uri| uri.as_str().len());
+    if uri_length > state.max_uri_length {
+        let response = HttpResponse::UriTooLong().body("414 URI Too Long");
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+    let size = req
+        .headers()
+        .get("Content-Length")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    if size.is_some_and(|size| size > state.max_request_size) {
+        let response = HttpResponse::PayloadTooLarge().body("413 Payload Too Large");
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+    Ok(next.call(req).await?.map_into_left_body())
+}
+
+/// Asks for the `basic_auth` credentials when they're set, before anything is served.
+/// `HOOK_ENDPOINT` is left to its own token, which scripts have to hand, and health checks
+/// to supervisors and load balancers, which don't log in. Posts API and admin requests
+/// bearing the `admin_token` get through too, since their `Authorization` header can't
+/// carry both
+async fn check_credentials(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let state = req.app_data::<web::Data<ServerState>>();
+    let admin_request = (req.path().starts_with(api::ENDPOINT)
+        || req.path().starts_with(admin::ENDPOINT))
+        && state
+            .and_then(|state| state.admin_token.as_deref())
+            .is_some_and(|token| has_bearer_token(req.request(), token));
+    let expected = state
+        .and_then(|state| state.basic_auth.clone())
+        .filter(|_| {
+            let path = req.path();
+            ![HOOK_ENDPOINT, status::HEALTH_ENDPOINT].contains(&path) && !admin_request
+        });
+    let Some(expected) = expected else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+    let given = req
+        .headers()
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|encoded| BASE64_STANDARD.decode(encoded.trim()).ok())
+        .unwrap_or_default();
+    if secure_eq(&given, expected.as_bytes()) {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+    let response = HttpResponse::Unauthorized()
+        .insert_header((
+            "WWW-Authenticate",
+            r#"Basic realm="Preview", charset="UTF-8""#,
+        ))
+        .body("401 Unauthorized");
+    Ok(req.into_response(response).map_into_right_body())
+}
+
+/// Compares a secret in full, so the time taken doesn't tell how much of a guess was right
+fn secure_eq(given: &[u8], expected: &[u8]) -> bool {
+    given.len() == expected.len()
+        && given
+            .iter()
+            .zip(expected)
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+/// Whether the request has `Authorization: Bearer <token>`
+fn has_bearer_token(req: &HttpRequest, token: &str) -> bool {
+    let given = header(req, "Authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    secure_eq(given.trim().as_bytes(), token.as_bytes())
+}
+
+/// Starts a rebuild for a request bearing the `webhook_token`, answering before it's done
+async fn rebuild_hook(req: HttpRequest, state: web::Data<ServerState>) -> HttpResponse {
+    let Some(token) = &state.webhook_token else {
+        return HttpResponse::NotFound().body("404 Not Found");
+    };
+    if !has_bearer_token(&req, token) {
+        return HttpResponse::Unauthorized()
+            .insert_header(("WWW-Authenticate", "Bearer"))
+            .body("401 Unauthorized");
+    }
+    println!("\n🪝 Rebuild requested through {}", HOOK_ENDPOINT);
+    if state.rebuild.send(Vec::new()).is_err() {
+        return HttpResponse::ServiceUnavailable().body("503 Rebuilds are not running");
+    }
+    HttpResponse::Accepted().body("202 Rebuild started")
 }
 
-async fn handle_request(req: HttpRequest) -> HttpResponse {
+async fn report_status(state: web::Data<ServerState>) -> HttpResponse {
+    state.build_status.response()
+}
+
+async fn health() -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header(("Cache-Control", "no-store"))
+        .body("ok")
+}
+
+async fn live_reload_events(state: web::Data<ServerState>) -> HttpResponse {
+    match &state.live_reload {
+        Some(live_reload) => live_reload.events(),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+async fn handle_request(req: HttpRequest, state: web::Data<ServerState>) -> HttpResponse {
     let path = req.path();
-    let mut file_path = path.trim_start_matches('/').to_string();
+    let output_dir = &state.output_dir;
 
-    // If path is empty or just "/", serve index.html
-    if file_path.is_empty() {
-        file_path = "index.html".to_string();
-    } else if !file_path.ends_with(".html") && !file_path.contains('.') {
-        // If no extension, try to append .html
-        file_path.push_str(".html");
+    // The site is static, so only reading it is allowed
+    match *req.method() {
+        Method::GET | Method::HEAD => {}
+        Method::OPTIONS => {
+            return HttpResponse::NoContent()
+                .insert_header(("Allow", ALLOWED_METHODS))
+                .finish()
+        }
+        _ => {
+            return HttpResponse::MethodNotAllowed()
+                .insert_header(("Allow", ALLOWED_METHODS))
+                .finish()
+        }
     }
 
-    let full_path = format!("./output/{}", file_path);
+    let files = state.output_cache.as_ref().map(cache::OutputCache::files);
+    let files = files.as_deref();
+
+    if let Some((location, status)) = find_redirect(files, output_dir, path).await {
+        return HttpResponse::build(status)
+            .insert_header(("Location", location))
+            .finish();
+    }
+    let full_path = match files {
+        Some(files) => request_file(path, |path| files.resolve(path)),
+        // Looking on disk canonicalizes paths, which blocks
+        None => {
+            let (output_dir, path) = (output_dir.clone(), path.to_string());
+            web::block(move || request_file(&path, |path| resolve_path(&output_dir, path)))
+                .await
+                .ok()
+                .flatten()
+        }
+    };
 
     // Try to serve the file
-    match std::fs::read(&full_path) {
-        Ok(content) => {
-            let content_type = if full_path.ends_with(".css") {
-                "text/css"
-            } else if full_path.ends_with(".js") {
-                "application/javascript"
-            } else if full_path.ends_with(".png") {
-                "image/png"
-            } else if full_path.ends_with(".jpg") || full_path.ends_with(".jpeg") {
-                "image/jpeg"
-            } else if full_path.ends_with(".gif") {
-                "image/gif"
-            } else if full_path.ends_with(".svg") {
-                "image/svg+xml"
-            } else {
-                "text/html; charset=utf-8"
-            };
+    let Some(full_path) = full_path else {
+        return not_found(&state, files).await;
+    };
+    let Some((length, modified)) = output_metadata(files, &full_path).await else {
+        return not_found(&state, files).await;
+    };
+    let content_type = content_type(&full_path.to_string_lossy(), &state.content_types);
+    let dev = state.live_reload.is_some();
+    let rule = if dev || state.cache_rules.is_empty() {
+        None
+    } else {
+        site_path(output_dir, &full_path)
+            .await
+            .and_then(|path| state.cache_rules.iter().find(|rule| rule.matches(&path)))
+    };
+    let cache_control = rule.map_or(cache_control(&content_type, dev), |rule| {
+        rule.cache_control.as_str()
+    });
+
+    let mut response = HttpResponse::Ok();
+    response
+        .content_type(content_type.as_str())
+        .insert_header(("Vary", "Accept-Encoding"))
+        .insert_header(("Cache-Control", cache_control));
+
+    // Send a pre-compressed copy from the build when the client accepts one
+    let inject_script = state.live_reload.is_some() && content_type.starts_with("text/html");
+    let mut precompressed = None;
+    for (encoding, extension) in [("br", "br"), ("gzip", "gz")] {
+        if inject_script || !accepts_encoding(&req, encoding) {
+            continue;
+        }
+        let compressed = PathBuf::from(format!("{}.{}", full_path.display(), extension));
+        if let Some(metadata) = output_metadata(files, &compressed).await {
+            precompressed = Some((encoding, compressed, metadata));
+            break;
+        }
+    }
+    let compressible = compress::is_compressible_type(&content_type);
+    let compressed_on_the_fly = state.compress && compressible && precompressed.is_none();
+    let as_is = precompressed.is_none() && !inject_script && !compressed_on_the_fly;
+    if let Some((encoding, _, _)) = &precompressed {
+        response.insert_header(("Content-Encoding", *encoding));
+    } else if as_is && state.compress {
+        // The compression middleware leaves responses with an encoding alone
+        response.insert_header(("Content-Encoding", "identity"));
+    }
+
+    // Files sent as they are from disk get ranges and conditional requests from
+    // actix-files, which streams them
+    let in_memory = files
+        .and_then(|files| files.get(&full_path))
+        .and_then(|file| file.content.clone());
+    if as_is && in_memory.is_none() {
+        let file = match NamedFile::open_async(&full_path).await {
+            Ok(file) => file.disable_content_disposition(),
+            Err(_) => return not_found(&state, files).await,
+        };
+        let mut file_response = file.into_response(&req);
+        let headers = file_response.headers_mut();
+        for (name, value) in response.finish().headers() {
+            headers.insert(name.clone(), value.clone());
+        }
+        return file_response;
+    }
+
+    // Tagged by the file sent, where a pre-compressed copy is a representation of its own.
+    // The tag is weak when the middleware may still compress the body
+    let (sent_length, sent_modified) = precompressed
+        .as_ref()
+        .map_or((length, modified), |(_, _, metadata)| *metadata);
+    let nanos = sent_modified
+        .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_nanos());
+    let weak = if compressed_on_the_fly { "W/" } else { "" };
+    let etag = format!("{}\"{:x}-{:x}\"", weak, sent_length, nanos);
+    response.insert_header(("ETag", etag.as_str()));
+    if let Some(modified) = modified {
+        response.insert_header(("Last-Modified", HttpDate::from(modified)));
+    }
+    if as_is {
+        response.insert_header(("Accept-Ranges", "bytes"));
+    }
+    if is_fresh(&req, &etag, modified) {
+        return response.status(StatusCode::NOT_MODIFIED).finish();
+    }
+    // HEAD gets the headers without the file being read or compressed, with the length it
+    // has in the output
+    if req.method() == Method::HEAD {
+        let no_body = futures_util::stream::empty::<Result<web::Bytes, actix_web::Error>>();
+        return response.body(SizedStream::new(sent_length, no_body));
+    }
+
+    // Bodies that are changed on the way out are read whole
+    if !as_is {
+        let content = match &precompressed {
+            Some((_, compressed, _)) => read_output(files, compressed).await,
+            None => read_output(files, &full_path).await,
+        };
+        return match content {
+            Some(content) if inject_script => response.body(livereload::inject_script(&content)),
+            Some(content) => response.body(content),
+            None => not_found(&state, files).await,
+        };
+    }
+
+    // Ranges are of the file itself, so they're only served for files sent as they are
+    let Some(content) = in_memory else {
+        return not_found(&state, files).await;
+    };
+    let range = header(&req, "Range")
+        .filter(|_| range_applies(&req, &etag, modified))
+        .map(|range| byte_range(range, length));
+    let range = match range {
+        Some(Ok(Some(range))) => {
+            response.status(StatusCode::PARTIAL_CONTENT).insert_header((
+                "Content-Range",
+                format!("bytes {}-{}/{}", range.start, range.end - 1, length),
+            ));
+            range
+        }
+        Some(Err(())) => {
+            return response
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .insert_header(("Content-Range", format!("bytes */{}", length)))
+                .finish();
+        }
+        Some(Ok(None)) | None => 0..length,
+    };
+    response.body(content.slice(range.start as usize..range.end as usize))
+}
+
+/// A generated file's contents, from the server's in-memory copy of the build when it
+/// keeps one
+async fn read_output(files: Option<&cache::Files>, path: &Path) -> Option<web::Bytes> {
+    if let Some(files) = files {
+        if let Some(content) = &files.get(path)?.content {
+            return Some(content.clone());
+        }
+        // Too large to keep in memory
+    }
+    tokio::fs::read(path).await.ok().map(web::Bytes::from)
+}
+
+/// A generated file's length and modification time, without reading it
+async fn output_metadata(
+    files: Option<&cache::Files>,
+    path: &Path,
+) -> Option<(u64, Option<SystemTime>)> {
+    if let Some(files) = files {
+        let file = files.get(path)?;
+        return Some((file.len, file.modified));
+    }
+    let metadata = tokio::fs::metadata(path).await.ok()?;
+    Some((metadata.len(), metadata.modified().ok()))
+}
 
-            HttpResponse::Ok().content_type(content_type).body(content)
+/// The bytes a `Range` header asks for out of `length`, or `Ok(None)` when the header
+/// should be ignored and the whole body sent: it isn't a single `bytes` range, or it's
+/// malformed. `Err` when the range lies past the end
+fn byte_range(header: &str, length: u64) -> Result<Option<Range<u64>>, ()> {
+    let Some((start, end)) = header
+        .trim()
+        .strip_prefix("bytes=")
+        .filter(|spec| !spec.contains(','))
+        .and_then(|spec| spec.split_once('-'))
+    else {
+        return Ok(None);
+    };
+    let (start, end) = (start.trim(), end.trim());
+    let range = if start.is_empty() {
+        // `bytes=-500` is the last 500 bytes
+        match end.parse::<u64>() {
+            Ok(0) => return Err(()),
+            Ok(suffix) => length.saturating_sub(suffix)..length,
+            Err(_) => return Ok(None),
         }
-        Err(_) => {
-            // If file not found, return 404
-            HttpResponse::NotFound().body("404 Not Found")
+    } else {
+        match (start.parse::<u64>(), end.parse::<u64>()) {
+            (Ok(start), _) if end.is_empty() => start..length,
+            (Ok(start), Ok(end)) if start <= end => start..length.min(end + 1),
+            _ => return Ok(None),
         }
+    };
+    if range.start >= length {
+        return Err(());
     }
+    Ok(Some(range))
 }
 
-fn setup_watcher(tx: mpsc::Sender<()>) -> notify::Result<()> {
-    let (watch_tx, watch_rx) = mpsc::channel();
+/// Whether a `Range` request is for the current file: without `If-Range`, or when it names
+/// the current `ETag` or modification time
+fn range_applies(req: &HttpRequest, etag: &str, modified: Option<SystemTime>) -> bool {
+    match header(req, "If-Range") {
+        None => true,
+        // Only strong tags validate ranges
+        Some(tag) if tag.starts_with('"') || tag.starts_with("W/") => {
+            tag == etag && !etag.starts_with("W/")
+        }
+        Some(date) => {
+            let date = date.parse::<HttpDate>().ok().map(SystemTime::from);
+            modified
+                .zip(date)
+                .is_some_and(|(modified, date)| unix_seconds(modified) == unix_seconds(date))
+        }
+    }
+}
 
+/// Pages and feeds are revalidated on every request so new posts show up right away, while
+/// other assets can be reused for an hour. The dev server always revalidates, which the
+/// `ETag` makes cheap
+fn cache_control(content_type: &str, dev: bool) -> &'static str {
+    let document = content_type.starts_with("text/html")
+        || content_type.contains("xml")
+        || content_type.contains("json");
+    if dev || document {
+        "no-cache"
+    } else {
+        "public, max-age=3600"
+    }
+}
+
+/// Whether the client's cached copy is still current: `If-None-Match` lists `etag` or,
+/// without that header, the file hasn't changed since `If-Modified-Since`
+fn is_fresh(req: &HttpRequest, etag: &str, modified: Option<SystemTime>) -> bool {
+    // Compared weakly, as for GET requests
+    let opaque = |tag: &str| tag.strip_prefix("W/").unwrap_or(tag).to_string();
+    if let Some(tags) = header(req, "If-None-Match") {
+        return tags
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || opaque(tag) == opaque(etag));
+    }
+    let since = header(req, "If-Modified-Since").and_then(|date| date.parse::<HttpDate>().ok());
+    match (modified, since) {
+        (Some(modified), Some(since)) => unix_seconds(modified) <= unix_seconds(since.into()),
+        _ => false,
+    }
+}
+
+/// A request header's value, if it's there and readable
+fn header<'a>(req: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+}
+
+/// HTTP dates have whole seconds, so times are compared by those
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// 404 response with the generated error page when there is one
+async fn not_found(state: &ServerState, files: Option<&cache::Files>) -> HttpResponse {
+    let page = match files {
+        Some(files) => files.resolve("404.html"),
+        None => Some(state.output_dir.join("404.html")),
+    };
+    let page = match page {
+        Some(page) => read_output(files, &page).await,
+        None => None,
+    };
+    match page {
+        Some(page) => HttpResponse::NotFound()
+            .content_type("text/html; charset=utf-8")
+            .body(match &state.live_reload {
+                Some(_) => livereload::inject_script(&page).into(),
+                None => page,
+            }),
+        None => HttpResponse::NotFound().body("404 Not Found"),
+    }
+}
+
+/// The output file a request path names, looked up with `resolve`. Pages live in
+/// `<dir>/index.html` so their URLs don't need an extension, and directory URLs like
+/// `/tags/` or `/archive/2024/` get the `index.html` inside. Other extensionless paths fall
+/// back to `<path>.html` for output from older builds, except under `.well-known/` where
+/// files are looked up by their exact name
+fn request_file(path: &str, resolve: impl Fn(&str) -> Option<PathBuf>) -> Option<PathBuf> {
+    let decoded = percent_decode_str(path).decode_utf8_lossy();
+    let file_path = decoded.trim_matches('/');
+    if file_path.is_empty() {
+        resolve("index.html")
+    } else if let Some(index) = resolve(&format!("{}/index.html", file_path)) {
+        Some(index)
+    } else if Path::new(file_path).extension().is_none() && !file_path.starts_with(".well-known/") {
+        resolve(&format!("{}.html", file_path))
+    } else {
+        resolve(file_path)
+    }
+}
+
+/// The file `path` (already percent-decoded) names under `root`, or `None` if there is no
+/// such file or it resolves outside `root`, e.g. through `..` or a symlink
+fn resolve_path(root: &Path, path: &str) -> Option<PathBuf> {
+    let root = root.canonicalize().ok()?;
+    let resolved = root.join(path).canonicalize().ok()?;
+    (resolved.starts_with(&root) && resolved.is_file()).then_some(resolved)
+}
+
+/// Site path of a file in the output directory, e.g. `/posts/hello/index.html`
+async fn site_path(output_dir: &Path, file: &Path) -> Option<String> {
+    let root = tokio::fs::canonicalize(output_dir).await.ok()?;
+    let relative = file.strip_prefix(root).ok()?;
+    let parts: Vec<_> = relative
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy())
+        .collect();
+    Some(format!("/{}", parts.join("/")))
+}
+
+/// `Content-Type` of a file by its extension, from `overrides` if it's there. Feeds get
+/// their own types, which the extension alone doesn't tell, and text types are marked as
+/// UTF-8
+fn content_type(path: &str, overrides: &BTreeMap<String, String>) -> String {
+    let extension = Path::new(path).extension().unwrap_or_default();
+    let custom = overrides
+        .iter()
+        .find(|(custom, _)| extension.eq_ignore_ascii_case(custom.as_str()));
+    if let Some((_, content_type)) = custom {
+        return content_type.clone();
+    }
+    let mime = if path.ends_with("rss.xml") || path.ends_with("/feed.xml") {
+        "application/rss+xml".to_string()
+    } else if path.ends_with("atom.xml") {
+        "application/atom+xml".to_string()
+    } else if path.ends_with("feed.json") {
+        "application/feed+json".to_string()
+    } else {
+        mime_guess::from_path(path)
+            .first_or_octet_stream()
+            .essence_str()
+            .to_string()
+    };
+    let textual = mime.starts_with("text/")
+        || mime.ends_with("+xml")
+        || mime.ends_with("+json")
+        || matches!(
+            mime.as_str(),
+            "application/javascript" | "application/json" | "application/xml"
+        );
+    if textual {
+        format!("{}; charset=utf-8", mime)
+    } else {
+        mime
+    }
+}
+
+/// Value of a `--name value` or `--name=value` command line option
+fn flag_value(name: &str) -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    args.iter().enumerate().find_map(|(i, arg)| {
+        if arg == name {
+            args.get(i + 1).cloned()
+        } else {
+            arg.strip_prefix(name)?
+                .strip_prefix('=')
+                .map(str::to_string)
+        }
+    })
+}
+
+/// Whether the request's `Accept-Encoding` header lists `encoding` without `q=0`
+fn accepts_encoding(req: &HttpRequest, encoding: &str) -> bool {
+    req.headers()
+        .get("Accept-Encoding")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value.split(',').any(|part| {
+                let mut params = part.split(';').map(str::trim);
+                params.next() == Some(encoding)
+                    && !params.any(|param| param == "q=0" || param == "q=0.0")
+            })
+        })
+}
+
+/// Looks up `path` in the generated `_redirects` table (`<from> <to> [status]` per line)
+async fn find_redirect(
+    files: Option<&cache::Files>,
+    output_dir: &Path,
+    path: &str,
+) -> Option<(String, StatusCode)> {
+    let table = match files {
+        Some(files) => files.resolve("_redirects")?,
+        None => output_dir.join("_redirects"),
+    };
+    let redirects = read_output(files, &table).await?;
+    let redirects = String::from_utf8_lossy(&redirects);
+    // Redirects are from extensionless paths, which old `.html` URLs match too
+    let path = path.trim_end_matches('/').trim_end_matches(".html");
+    redirects.lines().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        let (from, to) = (parts.next()?, parts.next()?);
+        if from.trim_end_matches('/') != path {
+            return None;
+        }
+        let status = parts
+            .next()
+            .and_then(|code| code.parse().ok())
+            .and_then(|code| StatusCode::from_u16(code).ok())
+            .unwrap_or(StatusCode::MOVED_PERMANENTLY);
+        Some((to.to_string(), status))
+    })
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Sends the changed files on `tx` whenever a file in one of `paths` (directories are watched recursively)
+/// is created, modified, renamed or removed, unless only `ignore_files` ones are. Paths that
+/// don't exist are skipped
+fn setup_watcher(
+    tx: mpsc::Sender<Vec<PathBuf>>,
+    paths: &[PathBuf],
+    ignore_files: Vec<String>,
+) -> notify::Result<RecommendedWatcher> {
     let mut watcher: RecommendedWatcher = Watcher::new(
         move |res: Result<notify::Event, notify::Error>| match res {
             Ok(event) => {
-                if matches!(event.kind, notify::EventKind::Modify(_)) {
-                    let _ = watch_tx.send(());
+                // Renames are modifications of the name
+                let relevant = matches!(
+                    event.kind,
+                    notify::EventKind::Create(_)
+                        | notify::EventKind::Modify(_)
+                        | notify::EventKind::Remove(_)
+                );
+                let changed: Vec<PathBuf> = event
+                    .paths
+                    .iter()
+                    .filter(|path| !config::is_ignored(&ignore_files, path))
+                    .cloned()
+                    .collect();
+                if relevant && (!changed.is_empty() || event.paths.is_empty()) {
+                    let _ = tx.send(changed);
                 }
             }
             Err(e) => eprintln!("Watch error: {:?}", e),
@@ -109,12 +1315,230 @@ fn setup_watcher(tx: mpsc::Sender<()>) -> notify::Result<()> {
         notify::Config::default(),
     )?;
 
-    watcher.watch(Path::new("posts"), RecursiveMode::Recursive)?;
+    for path in paths {
+        if path.is_dir() {
+            watcher.watch(path, RecursiveMode::Recursive)?;
+        } else if path.exists() {
+            watcher.watch(path, RecursiveMode::NonRecursive)?;
+        }
+    }
+
+    Ok(watcher)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // Keep watcher alive and relay events
-    for _ in watch_rx.iter() {
-        let _ = tx.send(());
+    /// An output directory with a page, next to a file that must not be served
+    fn output_dir(name: &str) -> PathBuf {
+        let base = std::env::temp_dir().join(format!("blog-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        std::fs::create_dir_all(base.join("output/posts/hello")).unwrap();
+        std::fs::write(base.join("output/posts/hello/index.html"), "hello").unwrap();
+        std::fs::write(base.join("output/index.html"), "home").unwrap();
+        std::fs::write(base.join("secret.txt"), "secret").unwrap();
+        base.join("output")
     }
 
-    Ok(())
+    /// Server state for serving `output_dir`, as in `dev` when `live_reload` is set
+    fn server_state(output_dir: PathBuf, live_reload: bool, memory: bool) -> ServerState {
+        ServerState {
+            output_cache: memory.then(|| cache::OutputCache::new(&output_dir).unwrap()),
+            output_dir,
+            live_reload: live_reload.then(livereload::LiveReload::new),
+            compress: false,
+            trailing_slash: true,
+            cache_rules: Vec::new(),
+            content_types: BTreeMap::new(),
+            basic_auth: None,
+            rate_limiter: None,
+            max_uri_length: 2048,
+            max_request_size: 1024,
+            webhook_token: None,
+            admin_token: None,
+            rebuild: mpsc::channel().0,
+            build_status: status::BuildStatus::default(),
+        }
+    }
+
+    fn resolve(root: &Path, request_path: &str) -> Option<PathBuf> {
+        let decoded = percent_decode_str(request_path).decode_utf8_lossy();
+        resolve_path(root, decoded.trim_matches('/'))
+    }
+
+    #[test]
+    fn resolves_files_inside_the_output_dir() {
+        let root = output_dir("inside");
+        let page = resolve(&root, "/posts/hello/index.html").unwrap();
+        assert!(page.ends_with("posts/hello/index.html"));
+        assert!(resolve(&root, "/posts/../posts/hello/index.html").is_some());
+        assert!(resolve(&root, "/posts/missing.html").is_none());
+        // Directories aren't files to serve
+        assert!(resolve(&root, "/posts/hello").is_none());
+    }
+
+    #[test]
+    fn serves_the_index_of_directory_urls() {
+        let root = output_dir("index");
+        let file = |path| request_file(path, |path| resolve_path(&root, path));
+        let page = file("/posts/hello/").unwrap();
+        assert!(page.ends_with("posts/hello/index.html"));
+        assert_eq!(file("/posts/hello"), Some(page));
+        assert!(file("/").unwrap().ends_with("output/index.html"));
+        // No index inside
+        assert_eq!(file("/posts/"), None);
+    }
+
+    #[test]
+    fn redirects_to_the_preferred_trailing_slash() {
+        let root = output_dir("slash");
+        let preferred = |path, trailing_slash| {
+            preferred_path(path, trailing_slash, |path| resolve_path(&root, path))
+        };
+        assert_eq!(preferred("/posts/hello", true), "/posts/hello/");
+        assert_eq!(preferred("/posts/hello/", false), "/posts/hello");
+        assert_eq!(preferred("/posts//hello", true), "/posts/hello/");
+        assert_eq!(preferred("//posts/hello/", true), "/posts/hello/");
+        assert_eq!(preferred("/index.html/", true), "/index.html");
+        // Left for the 404 page
+        assert_eq!(preferred("/posts/missing", true), "/posts/missing");
+        assert_eq!(preferred("//example.com", true), "//example.com");
+    }
+
+    #[test]
+    fn rejects_traversal_out_of_the_output_dir() {
+        let root = output_dir("traversal");
+        for path in [
+            "/../secret.txt",
+            "/posts/../../secret.txt",
+            "/..%2fsecret.txt",
+            "/..%2Fsecret.txt",
+            "/%2e%2e/secret.txt",
+            "/%2E%2E%2Fsecret.txt",
+            "/posts/hello/%2e%2e%2f%2e%2e%2f%2e%2e%2fsecret.txt",
+            "/%2fetc%2fpasswd",
+            "//etc/passwd",
+        ] {
+            assert_eq!(resolve(&root, path), None, "{}", path);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlinks_out_of_the_output_dir() {
+        let root = output_dir("symlink");
+        std::os::unix::fs::symlink(root.join("../secret.txt"), root.join("linked.txt")).unwrap();
+        assert_eq!(resolve(&root, "/linked.txt"), None);
+    }
+
+    #[test]
+    fn parses_byte_ranges() {
+        assert_eq!(byte_range("bytes=0-99", 1000), Ok(Some(0..100)));
+        assert_eq!(byte_range("bytes=100-", 1000), Ok(Some(100..1000)));
+        // The last 500 bytes, or the whole file when it's shorter
+        assert_eq!(byte_range("bytes=-500", 1000), Ok(Some(500..1000)));
+        assert_eq!(byte_range("bytes=-5000", 1000), Ok(Some(0..1000)));
+        // Ends past the file are cut short
+        assert_eq!(byte_range("bytes=900-5000", 1000), Ok(Some(900..1000)));
+        assert_eq!(byte_range(" bytes= 10 - 19 ", 1000), Ok(Some(10..20)));
+    }
+
+    #[test]
+    fn rejects_unsatisfiable_byte_ranges() {
+        assert_eq!(byte_range("bytes=1000-", 1000), Err(()));
+        assert_eq!(byte_range("bytes=1000-1999", 1000), Err(()));
+        assert_eq!(byte_range("bytes=-0", 1000), Err(()));
+        assert_eq!(byte_range("bytes=0-", 0), Err(()));
+    }
+
+    #[test]
+    fn ignores_unsupported_or_malformed_ranges() {
+        for header in [
+            "bytes=0-9,20-29",
+            "bytes=-",
+            "bytes=9-0",
+            "bytes=a-9",
+            "bytes=0-b",
+            "bytes=--5",
+            "items=0-9",
+            "0-9",
+            "",
+        ] {
+            assert_eq!(byte_range(header, 1000), Ok(None), "{}", header);
+        }
+    }
+
+    #[actix_web::test]
+    async fn answers_head_requests_without_a_body() {
+        use actix_web::test;
+
+        // With the live reload script to inject, from disk, and kept in memory as it is
+        for (live_reload, memory) in [(true, false), (false, true)] {
+            let state = server_state(output_dir("head"), live_reload, memory);
+            let app = test::init_service(
+                App::new()
+                    .app_data(web::Data::new(state))
+                    .default_service(web::route().to(handle_request)),
+            )
+            .await;
+            let get =
+                test::call_service(&app, test::TestRequest::get().uri("/").to_request()).await;
+            let get_etag = get.headers().get("ETag").cloned();
+            let head = test::TestRequest::default()
+                .method(Method::HEAD)
+                .uri("/")
+                .to_request();
+            let head = test::call_service(&app, head).await;
+            assert_eq!(head.status(), StatusCode::OK);
+            assert_eq!(
+                head.headers().get("Content-Type").unwrap(),
+                "text/html; charset=utf-8"
+            );
+            assert!(head.headers().contains_key("ETag"));
+            assert_eq!(head.headers().get("ETag").cloned(), get_etag);
+            // The length of `home`, sent as Content-Length without the body
+            assert_eq!(head.response().body().size(), BodySize::Sized(4));
+            assert!(test::read_body(head).await.is_empty());
+        }
+    }
+
+    #[actix_web::test]
+    async fn lets_only_the_admin_token_past_basic_auth() {
+        use actix_web::test;
+
+        let mut state = server_state(output_dir("auth"), false, false);
+        state.basic_auth = Some("user:password".to_string());
+        state.admin_token = Some("secret".to_string());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(state))
+                .wrap(middleware::from_fn(check_credentials))
+                .route(api::ENDPOINT, web::get().to(HttpResponse::Ok))
+                .default_service(web::route().to(handle_request)),
+        )
+        .await;
+        let status = |path: &str, authorization: Option<&str>| {
+            let mut request = test::TestRequest::get()
+                .uri(path)
+                .peer_addr("127.0.0.1:50000".parse().unwrap());
+            if let Some(authorization) = authorization {
+                request = request.insert_header(("Authorization", authorization.to_string()));
+            }
+            let request = request.to_request();
+            let app = &app;
+            async move { test::call_service(app, request).await.status() }
+        };
+
+        assert_eq!(status(api::ENDPOINT, None).await, StatusCode::UNAUTHORIZED);
+        let wrong = status(api::ENDPOINT, Some("Bearer wrong")).await;
+        assert_eq!(wrong, StatusCode::UNAUTHORIZED);
+        let bearer = status(api::ENDPOINT, Some("Bearer secret")).await;
+        assert_eq!(bearer, StatusCode::OK);
+        // The token is only for the API, not a way around the site's password
+        let page = status("/posts/hello/", Some("Bearer secret")).await;
+        assert_eq!(page, StatusCode::UNAUTHORIZED);
+        let basic = format!("Basic {}", BASE64_STANDARD.encode("user:password"));
+        assert_eq!(status("/posts/hello/", Some(&basic)).await, StatusCode::OK);
+    }
 }
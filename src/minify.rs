@@ -0,0 +1,148 @@
+// Elements whose contents are not HTML and must not have their whitespace collapsed
+const PRESERVED_ELEMENTS: &[&str] = &["pre", "textarea", "script", "style"];
+
+/// Removes comments and collapses runs of whitespace to a single space. `<pre>` and
+/// `<textarea>` contents are left untouched, inline `<style>` and `<script>` are minified
+/// as CSS and JS
+pub fn minify_html(html: &str) -> String {
+    let mut result = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while !rest.is_empty() {
+        // Drop comments, but keep conditional comments which browsers act on
+        if rest.starts_with("<!--") && !rest.starts_with("<!--[if") {
+            match rest.find("-->") {
+                Some(end) => {
+                    rest = &rest[end + 3..];
+                    continue;
+                }
+                None => break,
+            }
+        }
+
+        if let Some(element) = preserved_element_at(rest) {
+            let closing = format!("</{}", element);
+            let end = rest[1..]
+                .to_ascii_lowercase()
+                .find(&closing)
+                .map(|i| i + 1)
+                .unwrap_or(rest.len());
+            let (block, after) = rest.split_at(end);
+
+            // Inline styles and scripts get their own minifier, the rest is copied as-is
+            let (open_tag, content) =
+                block.split_at(block.find('>').map_or(block.len(), |i| i + 1));
+            result.push_str(open_tag);
+            match element {
+                "style" => result.push_str(&minify_css(content)),
+                "script" => result.push_str(&minify_js(content)),
+                _ => result.push_str(content),
+            }
+            rest = after;
+            continue;
+        }
+
+        let ch = rest.chars().next().unwrap();
+        if ch.is_whitespace() {
+            let end = rest
+                .find(|c: char| !c.is_whitespace())
+                .unwrap_or(rest.len());
+            if !result.is_empty() && end < rest.len() {
+                result.push(' ');
+            }
+            rest = &rest[end..];
+        } else {
+            result.push(ch);
+            rest = &rest[ch.len_utf8()..];
+        }
+    }
+
+    result
+}
+
+/// Removes comments and insignificant whitespace from a stylesheet
+pub fn minify_css(css: &str) -> String {
+    let mut result = String::with_capacity(css.len());
+    let mut chars = css.chars().peekable();
+    let mut pending_space = false;
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            // Strings are copied verbatim
+            '"' | '\'' => {
+                if pending_space {
+                    result.push(' ');
+                    pending_space = false;
+                }
+                result.push(ch);
+                while let Some(inner) = chars.next() {
+                    result.push(inner);
+                    if inner == '\\' {
+                        if let Some(escaped) = chars.next() {
+                            result.push(escaped);
+                        }
+                    } else if inner == ch {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for inner in chars.by_ref() {
+                    if previous == '*' && inner == '/' {
+                        break;
+                    }
+                    previous = inner;
+                }
+                pending_space = !result.is_empty();
+            }
+            c if c.is_whitespace() => pending_space = !result.is_empty(),
+            // Punctuation that never needs surrounding whitespace. Spaces before `:` are
+            // kept because `a :hover` and `a:hover` are different selectors
+            '{' | '}' | ';' | ',' | '>' | ':' => {
+                if ch == '}' && result.ends_with(';') {
+                    result.pop();
+                }
+                if ch == ':' && pending_space {
+                    result.push(' ');
+                }
+                result.push(ch);
+                pending_space = false;
+                while chars.peek().is_some_and(|c| c.is_whitespace()) {
+                    chars.next();
+                }
+            }
+            _ => {
+                let after_punctuation = result.ends_with(['{', '}', ';', ',', '>', ':']);
+                if pending_space && !after_punctuation {
+                    result.push(' ');
+                }
+                pending_space = false;
+                result.push(ch);
+            }
+        }
+    }
+
+    result
+}
+
+/// Conservative script minification: trims indentation and drops blank lines and
+/// whole-line `//` comments, keeping line breaks so automatic semicolon insertion still works
+pub fn minify_js(js: &str) -> String {
+    js.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("//"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Returns the element name if `html` starts with an opening tag of a preserved element
+fn preserved_element_at(html: &str) -> Option<&'static str> {
+    let tag = html.strip_prefix('<')?;
+    PRESERVED_ELEMENTS.iter().copied().find(|element| {
+        tag.get(..element.len())
+            .is_some_and(|name| name.eq_ignore_ascii_case(element))
+            && tag[element.len()..].starts_with(|c: char| c == '>' || c.is_whitespace())
+    })
+}
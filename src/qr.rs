@@ -0,0 +1,28 @@
+use qrcode::{Color, EcLevel, QrCode};
+
+/// Renders `text` as a QR code for the terminal, two rows of modules per line with
+/// explicit colors so it scans on dark and light themes alike. The lowest error
+/// correction level keeps it small. `None` if it's too long for a QR code
+pub fn render(text: &str) -> Option<String> {
+    let code = QrCode::with_error_correction_level(text, EcLevel::L).ok()?;
+    let colors = code.to_colors();
+    // The standard asks for a wider quiet zone, but two modules is enough for phones
+    let quiet = 2;
+    let size = code.width() as isize;
+    let dark = |x: isize, y: isize| {
+        (0..size).contains(&x)
+            && (0..size).contains(&y)
+            && colors[(y * size + x) as usize] == Color::Dark
+    };
+
+    let mut output = String::new();
+    for y in (-quiet..size + quiet).step_by(2) {
+        for x in -quiet..size + quiet {
+            let top = if dark(x, y) { 30 } else { 97 };
+            let bottom = if dark(x, y + 1) { 40 } else { 107 };
+            output.push_str(&format!("\x1b[{};{}m▀", top, bottom));
+        }
+        output.push_str("\x1b[0m\n");
+    }
+    Some(output)
+}
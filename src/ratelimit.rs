@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Clients tracked before the ones with a full allowance are forgotten
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// Allows each client address `per_minute` requests a minute. Bursts of up to that many
+/// are fine, after which requests are let through as the allowance refills
+pub struct RateLimiter {
+    per_minute: u32,
+    /// Requests each client has left, as of when it last made one
+    clients: Mutex<HashMap<IpAddr, (f64, Instant)>>,
+}
+
+impl RateLimiter {
+    pub fn new(per_minute: u32) -> Self {
+        RateLimiter {
+            per_minute,
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Counts a request from `client`, or tells how long until it may make one
+    pub fn check(&self, client: IpAddr) -> Result<(), Duration> {
+        let capacity = f64::from(self.per_minute);
+        let refill = |left: f64, since: Instant| {
+            (left + since.elapsed().as_secs_f64() * capacity / 60.0).min(capacity)
+        };
+        let mut clients = self.clients.lock().unwrap();
+        if clients.len() >= MAX_TRACKED_CLIENTS {
+            clients.retain(|_, (left, since)| refill(*left, *since) < capacity);
+        }
+
+        let now = Instant::now();
+        let (left, since) = clients.entry(client).or_insert((capacity, now));
+        let available = refill(*left, *since);
+        if available < 1.0 {
+            return Err(Duration::from_secs_f64((1.0 - available) * 60.0 / capacity));
+        }
+        *left = available - 1.0;
+        *since = now;
+        Ok(())
+    }
+}
@@ -0,0 +1,144 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+// Warnings raised during the current build, listed again in the build report
+static WARNINGS: OnceLock<Mutex<Vec<String>>> = OnceLock::new();
+
+// Files that couldn't be built, with the reason
+static ERRORS: OnceLock<Mutex<Vec<String>>> = OnceLock::new();
+
+/// Prints a build warning and keeps it for the build report
+pub fn warn(message: impl Into<String>) {
+    let message = message.into();
+    eprintln!("⚠️  {}", message);
+    WARNINGS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap()
+        .push(message);
+}
+
+/// Prints a problem that kept a file out of the build and keeps it for the build result
+pub fn error(message: impl Into<String>) {
+    let message = message.into();
+    eprintln!("❌ {}", message);
+    ERRORS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap()
+        .push(message);
+}
+
+/// Problems recorded so far in the current build
+pub fn errors() -> Vec<String> {
+    ERRORS.get_or_init(Default::default).lock().unwrap().clone()
+}
+
+/// Statistics about one build, printed at the end and optionally saved as JSON
+pub struct BuildReport {
+    started: Instant,
+    last_stage: Instant,
+    stages: Vec<(&'static str, Duration)>,
+    pub posts: usize,
+    pub pages: usize,
+}
+
+impl BuildReport {
+    pub fn start() -> Self {
+        WARNINGS
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap()
+            .clear();
+        ERRORS.get_or_init(Default::default).lock().unwrap().clear();
+        let now = Instant::now();
+        BuildReport {
+            started: now,
+            last_stage: now,
+            stages: Vec::new(),
+            posts: 0,
+            pages: 0,
+        }
+    }
+
+    /// Records the time since the previous stage finished
+    pub fn stage(&mut self, name: &'static str) {
+        let now = Instant::now();
+        self.stages.push((name, now - self.last_stage));
+        self.last_stage = now;
+    }
+
+    /// Prints the report, and returns it as JSON. `outputs` are the files this build produced
+    pub fn finish(&self, outputs: &[PathBuf]) -> serde_json::Value {
+        let total = self.started.elapsed();
+        let warnings = WARNINGS.get_or_init(Default::default).lock().unwrap();
+        let errors = errors();
+        let output_bytes: u64 = outputs
+            .iter()
+            .filter_map(|path| fs::metadata(path).ok())
+            .map(|metadata| metadata.len())
+            .sum();
+        // Images, static files and bundle assets, as opposed to generated pages and data
+        let assets = outputs
+            .iter()
+            .filter(|path| {
+                let extension = path
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .unwrap_or_default();
+                !matches!(extension, "" | "html" | "xml" | "json" | "gz" | "br")
+            })
+            .count();
+
+        println!(
+            "📊 {} posts, {} pages, {} assets → {} files ({}) in {} ms",
+            self.posts,
+            self.pages,
+            assets,
+            outputs.len(),
+            format_size(output_bytes),
+            total.as_millis()
+        );
+        let timings: Vec<_> = self
+            .stages
+            .iter()
+            .map(|(name, duration)| format!("{} {} ms", name, duration.as_millis()))
+            .collect();
+        println!("   {}", timings.join(" · "));
+        if !warnings.is_empty() {
+            println!("   {} warnings", warnings.len());
+        }
+        if !errors.is_empty() {
+            println!("   {} errors", errors.len());
+        }
+
+        serde_json::json!({
+            "posts": self.posts,
+            "pages": self.pages,
+            "assets": assets,
+            "output_files": outputs.len(),
+            "output_bytes": output_bytes,
+            "total_ms": total.as_millis() as u64,
+            "stages": self
+                .stages
+                .iter()
+                .map(|(name, duration)| serde_json::json!({
+                    "name": name,
+                    "ms": duration.as_millis() as u64,
+                }))
+                .collect::<Vec<_>>(),
+            "warnings": *warnings,
+            "errors": errors,
+        })
+    }
+}
+
+pub fn format_size(bytes: u64) -> String {
+    match bytes {
+        0..=1023 => format!("{} B", bytes),
+        1024..=1_048_575 => format!("{:.1} KB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MB", bytes as f64 / 1_048_576.0),
+    }
+}
@@ -0,0 +1,123 @@
+use crate::generator::render;
+use crate::report;
+use std::collections::BTreeMap;
+use tera::{Context, Tera};
+
+/// Where shortcode templates live, inside the template directories
+const SHORTCODES_DIR: &str = "shortcodes";
+
+/// Expands `{{ name(key="value", n=3) }}` shortcodes in rendered post HTML by rendering
+/// `templates/shortcodes/<name>.html` with the arguments as variables. A shortcode that
+/// is a paragraph of its own replaces the whole `<p>`, so it can produce block elements.
+/// Code blocks are left alone, and so are shortcodes without a template, with a warning.
+/// Returns the expanded HTML or a message for the first shortcode that failed to render
+pub fn expand(tera: &Tera, html: &str) -> Result<String, String> {
+    let mut result = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = rest.find("{{") {
+        // Examples in code blocks must show the shortcode itself
+        if let Some(pre) = rest[..start].find("<pre>") {
+            let end = rest[pre..]
+                .find("</pre>")
+                .map_or(rest.len(), |i| pre + i + "</pre>".len());
+            result.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+        let Some(length) = rest[start..].find("}}") else {
+            break;
+        };
+        let end = start + length + "}}".len();
+
+        let Some((name, args)) = parse_call(rest[start + 2..end - 2].trim()) else {
+            result.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        };
+        let template = format!("{}/{}.html", SHORTCODES_DIR, name);
+        if !tera.get_template_names().any(|t| t == template) {
+            report::warn(format!(
+                "Unknown shortcode `{}`, there is no {}",
+                name, template
+            ));
+            result.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+
+        let mut context = Context::new();
+        for (key, value) in &args {
+            context.insert(key.as_str(), value);
+        }
+        let rendered = render(tera, &template, &context, &format!("shortcode `{}`", name))?;
+
+        let (mut before, mut after) = (&rest[..start], &rest[end..]);
+        if let (Some(p_before), Some(p_after)) =
+            (before.strip_suffix("<p>"), after.strip_prefix("</p>"))
+        {
+            (before, after) = (p_before, p_after);
+        }
+        result.push_str(before);
+        result.push_str(rendered.trim());
+        rest = after;
+    }
+
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Splits `name(key=value, ...)` into the name and its arguments. Values are double-quoted
+/// strings, numbers or booleans
+fn parse_call(call: &str) -> Option<(&str, BTreeMap<String, serde_json::Value>)> {
+    let (name, args) = call.strip_suffix(')')?.split_once('(')?;
+    let name = name.trim();
+    let valid_name = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+    if name.is_empty() || !name.chars().all(valid_name) {
+        return None;
+    }
+
+    let mut parsed = BTreeMap::new();
+    let mut rest = args.trim();
+    while !rest.is_empty() {
+        let (key, value) = rest.split_once('=')?;
+        let key = key.trim();
+        if key.is_empty() || !key.chars().all(valid_name) {
+            return None;
+        }
+        let value = value.trim_start();
+        let (value, after) = match value.strip_prefix('"') {
+            Some(quoted) => {
+                let mut text = String::new();
+                let mut chars = quoted.char_indices();
+                let mut end = None;
+                while let Some((i, c)) = chars.next() {
+                    match c {
+                        '\\' => text.extend(chars.next().map(|(_, c)| c)),
+                        '"' => {
+                            end = Some(i + 1);
+                            break;
+                        }
+                        c => text.push(c),
+                    }
+                }
+                (serde_json::Value::String(text), &quoted[end?..])
+            }
+            None => {
+                let end = value.find(',').unwrap_or(value.len());
+                let literal = match value[..end].trim() {
+                    "true" => serde_json::Value::Bool(true),
+                    "false" => serde_json::Value::Bool(false),
+                    number => serde_json::from_str::<serde_json::Number>(number)
+                        .ok()?
+                        .into(),
+                };
+                (literal, &value[end..])
+            }
+        };
+        parsed.insert(key.to_string(), value);
+        let after = after.trim_start();
+        rest = after.strip_prefix(',').unwrap_or(after).trim_start();
+    }
+    Some((name, parsed))
+}
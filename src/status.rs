@@ -0,0 +1,46 @@
+use actix_web::HttpResponse;
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// JSON about the latest build, for checking on a running server
+pub const STATUS_ENDPOINT: &str = "/__status";
+
+/// Answers 200 while the server is up, for supervisors and load balancers
+pub const HEALTH_ENDPOINT: &str = "/__health";
+
+/// Outcome of the latest build, shared between the rebuild thread and `STATUS_ENDPOINT`
+#[derive(Clone, Default)]
+pub struct BuildStatus(Arc<Mutex<Status>>);
+
+#[derive(Default, Serialize)]
+struct Status {
+    /// When the latest successful build finished, in RFC 3339
+    last_build: Option<String>,
+    duration_ms: Option<u128>,
+    posts: Option<usize>,
+    /// Why the latest build failed, or the problems it ran into. The site keeps the
+    /// previous successful build's files when one fails
+    last_error: Option<String>,
+}
+
+impl BuildStatus {
+    pub fn succeeded(&self, duration: Duration, posts: usize, problems: &[String]) {
+        let mut status = self.0.lock().unwrap();
+        status.last_build = Some(chrono::Local::now().to_rfc3339());
+        status.duration_ms = Some(duration.as_millis());
+        status.posts = Some(posts);
+        status.last_error = (!problems.is_empty()).then(|| problems.join("\n"));
+    }
+
+    pub fn failed(&self, error: &str) {
+        self.0.lock().unwrap().last_error = Some(error.to_string());
+    }
+
+    /// Response for `STATUS_ENDPOINT`
+    pub fn response(&self) -> HttpResponse {
+        HttpResponse::Ok()
+            .insert_header(("Cache-Control", "no-store"))
+            .json(&*self.0.lock().unwrap())
+    }
+}
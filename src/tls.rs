@@ -0,0 +1,50 @@
+use crate::config::SiteConfig;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use rustls::ServerConfig;
+use std::io;
+use std::net::IpAddr;
+
+/// TLS settings for serving HTTPS, from the `tls_cert` and `tls_key` files or else from a
+/// self-signed certificate for this machine, made for this run only
+pub fn server_config(site: &SiteConfig, lan_address: Option<IpAddr>) -> io::Result<ServerConfig> {
+    let (certs, key) = match (&site.server.tls_cert, &site.server.tls_key) {
+        (Some(cert), Some(key)) => {
+            let certs = CertificateDer::pem_file_iter(cert)
+                .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+                .map_err(|e| invalid(format!("{}: {}", cert, e)))?;
+            let key = PrivateKeyDer::from_pem_file(key)
+                .map_err(|e| invalid(format!("{}: {}", key, e)))?;
+            (certs, key)
+        }
+        _ => self_signed(lan_address)?,
+    };
+    ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| invalid(e.to_string()))
+}
+
+/// A certificate for `localhost`, the loopback addresses and the network address, which
+/// browsers warn about until an exception is added
+fn self_signed(
+    lan_address: Option<IpAddr>,
+) -> io::Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+    let mut names = vec![
+        "localhost".to_string(),
+        "127.0.0.1".to_string(),
+        "::1".to_string(),
+    ];
+    names.extend(lan_address.map(|ip| ip.to_string()));
+    let generated =
+        rcgen::generate_simple_self_signed(names).map_err(|e| invalid(e.to_string()))?;
+    println!(
+        "🔐 Serving HTTPS with a self-signed certificate; set tls_cert and tls_key to use your own"
+    );
+    let key = PrivatePkcs8KeyDer::from(generated.signing_key.serialize_der());
+    Ok((vec![generated.cert.der().clone()], key.into()))
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}